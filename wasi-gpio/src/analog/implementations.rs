@@ -1,19 +1,38 @@
-use super::{AnalogConfigBuilder, AnalogOutPin};
+use super::{AnalogConfigBuilder, AnalogOutPin, AnalogOutput};
+use crate::policies;
 use crate::wasi::gpio::{analog, general};
 
+/// Period of the PWM that drives an analog output, 1 kHz
+const PWM_PERIOD: std::time::Duration = std::time::Duration::from_millis(1);
+
+/// Period of the pulses that drive an RC servo, 50 Hz
+pub const SERVO_PERIOD: std::time::Duration = std::time::Duration::from_millis(20);
+
 impl AnalogConfigBuilder {
     pub fn new(label: String, pin_mode: general::PinMode) -> Self {
         Self {
             label,
             pin_mode,
             output_mode: None,
+            flags: Vec::new(),
+            constraints: policies::PinConstraints::default(),
         }
     }
 
+    /// Applies the constraints the policy puts on the pin
+    pub fn constrain(mut self, constraints: policies::PinConstraints) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
     pub fn add_flags(mut self, flags: Vec<analog::AnalogFlag>) -> Self {
+        self.flags.extend(flags.iter().copied());
+
         for flag in flags {
             if flag == analog::AnalogFlag::PWM {
                 self.output_mode = Some(analog::OutputMode::Pwm)
+            } else if flag == analog::AnalogFlag::SERVO {
+                self.output_mode = Some(analog::OutputMode::Servo)
             }
         }
 
@@ -21,11 +40,24 @@ impl AnalogConfigBuilder {
     }
 
     pub fn build(self) -> Result<analog::AnalogConfig, general::GpioError> {
+        if self.constraints.denies_analog(&self.flags) {
+            return Err(general::GpioError::InvalidFlag);
+        }
+
+        let frequency = match self.output_mode {
+            Some(analog::OutputMode::Servo) => 1. / SERVO_PERIOD.as_secs_f64(),
+            _ => 1. / PWM_PERIOD.as_secs_f64(),
+        };
+
         match self.pin_mode {
             general::PinMode::Out => {
                 if self.output_mode.is_none() {
                     return Err(general::GpioError::InvalidFlag);
                 }
+
+                if !self.constraints.allows_frequency(frequency) {
+                    return Err(general::GpioError::PinModeNotAllowed);
+                }
             }
             general::PinMode::In => return Err(general::GpioError::PinModeNotAvailable),
         }
@@ -38,9 +70,47 @@ impl AnalogConfigBuilder {
     }
 }
 
+impl AnalogOutput {
+    fn set_pwm(&mut self, period: std::time::Duration, duty_cycle: f64) -> Result<(), String> {
+        match self {
+            AnalogOutput::Hardware(pwm) => pwm
+                .set_frequency(1. / period.as_secs_f64(), duty_cycle)
+                .and_then(|_| pwm.enable())
+                .map_err(|err| err.to_string()),
+            AnalogOutput::Software(pin) => pin
+                .set_pwm(period, period.mul_f64(duty_cycle))
+                .map_err(|err| err.to_string()),
+        }
+    }
+}
+
 impl AnalogOutPin {
-    pub fn new(pin: rppal::gpio::OutputPin, config: analog::AnalogConfig) -> Self {
-        Self { pin, config }
+    pub fn new(
+        pin: AnalogOutput,
+        config: analog::AnalogConfig,
+        servo_limits: policies::ServoLimits,
+        constraints: policies::PinConstraints,
+    ) -> Self {
+        Self {
+            pin,
+            config,
+            servo_limits,
+            constraints,
+            value: None,
+        }
+    }
+
+    /// Leaves the pin as it is when dropped instead of resetting it, for pins held by their safe state
+    pub fn keep_state(&mut self) {
+        match &mut self.pin {
+            AnalogOutput::Hardware(pwm) => pwm.set_reset_on_drop(false),
+            AnalogOutput::Software(pin) => pin.set_reset_on_drop(false),
+        }
+    }
+
+    /// Returns true when writing `value` changes the output
+    pub fn changes(&self, value: f32) -> bool {
+        self.value != Some(value.clamp(0., 1.))
     }
 
     pub fn get_config(&self) -> analog::AnalogConfig {
@@ -48,9 +118,31 @@ impl AnalogOutPin {
     }
 
     pub fn set_value(&mut self, value: f32) -> Result<(), String> {
+        // Clamping keeps NaN, which no duty cycle or pulse width can be made of
+        if !value.is_finite() {
+            return Err("Invalid value".to_string());
+        }
+
+        let clamped = value.clamp(0., 1.);
+        let value = clamped as f64;
+
+        let (period, duty_cycle) = match self.config.output_mode {
+            Some(analog::OutputMode::Servo) => {
+                let min = self.servo_limits.min_pulse_us as f64;
+                let max = self.servo_limits.max_pulse_us as f64;
+                let pulse = (min + value * (max - min)) / 1e6;
+
+                (SERVO_PERIOD, pulse / SERVO_PERIOD.as_secs_f64())
+            }
+            _ => (PWM_PERIOD, value),
+        };
+
+        // The duty cycle limit of the pin caps servo pulses as well
         self.pin
-            .set_pwm_frequency(1000., value as f64)
-            .map_err(|err| err.to_string())
+            .set_pwm(period, self.constraints.limit_duty_cycle(duty_cycle))?;
+
+        self.value = Some(clamped);
+        Ok(())
     }
 }
 
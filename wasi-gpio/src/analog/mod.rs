@@ -2,7 +2,7 @@ pub mod implementations;
 use crate::ctx::WasiGpioView;
 use crate::impls::GpioImpl;
 use crate::policies;
-use crate::poll::Pollable;
+use crate::poll::PollableResource;
 use crate::wasi::gpio::{analog, general};
 use wasmtime::component::Resource;
 
@@ -16,40 +16,50 @@ pub struct AnalogConfigBuilder {
     label: String,
     pin_mode: general::PinMode,
     output_mode: Option<analog::OutputMode>,
+    flags: Vec<analog::AnalogFlag>,
+    constraints: policies::PinConstraints,
 }
 
 pub struct AnalogInPin {}
 
 pub struct AnalogInOutPin {}
 
+/// The way the PWM of an analog output is generated
+pub enum AnalogOutput {
+    Hardware(rppal::pwm::Pwm),
+    Software(rppal::gpio::OutputPin),
+}
+
 pub struct AnalogOutPin {
-    pub pin: rppal::gpio::OutputPin,
+    pub pin: AnalogOutput,
     pub config: analog::AnalogConfig,
+    pub servo_limits: policies::ServoLimits,
+    pub constraints: policies::PinConstraints,
+    /// Last value written, none before the first write
+    pub value: Option<f32>,
 }
 
 // Helper function to resolve pins based on policies
 fn get_pin_output(
     ctx: &crate::ctx::WasiGpioCtx,
     label: &str,
-) -> Result<rppal::gpio::OutputPin, general::GpioError> {
-    let plabel = ctx
+) -> Result<AnalogOutput, general::GpioError> {
+    let pin = ctx
         .policies
-        .get_plabel(label)
+        .get_pin(label)
         .ok_or_else(|| general::GpioError::Other("Pin not found in policy".to_string()))?;
 
-    // Parse "GPIO<num>"
-    let pin_num = plabel
-        .strip_prefix("GPIO")
-        .and_then(|s| s.parse::<u8>().ok())
-        .ok_or_else(|| general::GpioError::Other("Invalid physical label format".to_string()))?;
-
     let gpio = rppal::gpio::Gpio::new().map_err(|e| general::GpioError::Other(e.to_string()))?;
 
+    if let Some(pwm) = crate::tone::get_hardware_pwm(&gpio, pin) {
+        return Ok(AnalogOutput::Hardware(pwm));
+    }
+
     let pin = gpio
-        .get(pin_num)
+        .get(pin.bcm)
         .map_err(|e| general::GpioError::Other(e.to_string()))?;
 
-    Ok(pin.into_output_low())
+    Ok(AnalogOutput::Software(pin.into_output_low()))
 }
 
 impl<'a, T: WasiGpioView> analog::Host for GpioImpl<'a, T> {}
@@ -68,18 +78,48 @@ impl<'a, T: WasiGpioView> analog::HostAnalogOutPin for GpioImpl<'a, T> {
             return Err(general::GpioError::PinModeNotAllowed);
         }
 
+        // A PWM output is switched on for part of every period, whatever the other interlocked pins do
+        if self.ctx().policies.is_interlocked(&pin_label) {
+            return Err(general::GpioError::PinModeNotAllowed);
+        }
+
         implementations::check_invalid_flags(&flags, vec![analog::AnalogFlag::DAC])
             .map_err(|_| general::GpioError::InvalidFlag)?;
 
         let pin = get_pin_output(self.ctx(), &pin_label)?;
 
+        let servo_limits = self
+            .ctx()
+            .policies
+            .get_servo_limits(&pin_label)
+            .ok_or_else(|| general::GpioError::Other("Pin not found in policy".to_string()))?;
+
+        if servo_limits.min_pulse_us >= servo_limits.max_pulse_us {
+            return Err(general::GpioError::Other(
+                "Invalid servo pulse range in policy".to_string(),
+            ));
+        }
+
+        let constraints = self
+            .ctx()
+            .policies
+            .get_constraints(&pin_label)
+            .unwrap_or_default();
+
         let config = AnalogConfigBuilder::new(pin_label, general::PinMode::Out)
             .add_flags(flags)
+            .constrain(constraints.clone())
             .build()
             .map_err(|_| general::GpioError::InvalidFlag)?;
 
+        let mut pin = AnalogOutPin::new(pin, config, servo_limits, constraints);
+
+        if self.keeps_state(&pin.config.label) {
+            pin.keep_state();
+        }
+
         self.table()
-            .push(AnalogOutPin::new(pin, config))
+            .push(pin)
             .map_err(|err| general::GpioError::Other(err.to_string()))
     }
 
@@ -119,18 +159,36 @@ impl<'a, T: WasiGpioView> analog::HostAnalogOutPin for GpioImpl<'a, T> {
         self_: Resource<AnalogOutPin>,
         value: f32,
     ) -> Result<(), general::GpioError> {
+        let pin = self
+            .table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?;
+
+        let label = pin.config.label.clone();
+        let changes = pin.changes(value);
+
+        if changes {
+            self.limit_rate(&label)?;
+        }
+
         let pin = self
             .table()
             .get_mut(&self_)
             .map_err(|_| general::GpioError::ResourceInvalidated)?;
-        Ok(pin
-            .set_value(value)
-            .map_err(|err| general::GpioError::Other(err))?)
+        pin.set_value(value)
+            .map_err(|err| general::GpioError::Other(err))?;
+
+        if changes {
+            self.record_change(&label);
+        }
+        Ok(())
     }
 
     fn drop(&mut self, rep: Resource<AnalogOutPin>) -> wasmtime::Result<()> {
-        self.table().delete(rep).expect("failed to delete resource");
-        Ok(())
+        let pin = self.table().delete(rep).expect("failed to delete resource");
+        let label = pin.config.label.clone();
+        std::mem::drop(pin);
+        self.apply_safe_state(&label)
     }
 }
 
@@ -230,7 +288,7 @@ impl<'a, T: WasiGpioView> analog::HostAnalogInPin for GpioImpl<'a, T> {
         &mut self,
         _self_: Resource<AnalogInPin>,
         _value: u32,
-    ) -> Result<Resource<Pollable>, general::GpioError> {
+    ) -> Result<Resource<PollableResource>, general::GpioError> {
         Err(general::GpioError::ResourceInvalidated)
     }
 
@@ -238,7 +296,7 @@ impl<'a, T: WasiGpioView> analog::HostAnalogInPin for GpioImpl<'a, T> {
         &mut self,
         _self_: Resource<AnalogInPin>,
         _value: f32,
-    ) -> Result<Resource<Pollable>, general::GpioError> {
+    ) -> Result<Resource<PollableResource>, general::GpioError> {
         Err(general::GpioError::ResourceInvalidated)
     }
 
@@ -246,7 +304,7 @@ impl<'a, T: WasiGpioView> analog::HostAnalogInPin for GpioImpl<'a, T> {
         &mut self,
         _self_: Resource<AnalogInPin>,
         _value: u32,
-    ) -> Result<Resource<Pollable>, general::GpioError> {
+    ) -> Result<Resource<PollableResource>, general::GpioError> {
         Err(general::GpioError::ResourceInvalidated)
     }
 
@@ -254,7 +312,7 @@ impl<'a, T: WasiGpioView> analog::HostAnalogInPin for GpioImpl<'a, T> {
         &mut self,
         _self_: Resource<AnalogInPin>,
         _value: f32,
-    ) -> Result<Resource<Pollable>, general::GpioError> {
+    ) -> Result<Resource<PollableResource>, general::GpioError> {
         Err(general::GpioError::ResourceInvalidated)
     }
 
@@ -0,0 +1,243 @@
+//! Bindings for embeddings that run wasmtime with `async_support`.
+//! Only the blocking calls differ from the synchronous bindings, every other interface is shared with them.
+//! Writes to a pin with a rate limit that delays changes stay synchronous and sleep the thread of the store.
+
+use crate::ctx::WasiGpioView;
+use crate::impls::GpioImpl;
+use crate::poll::{PollableResource, cancellable};
+use wasmtime::component::Resource;
+
+wasmtime::component::bindgen!({
+    path: "../wit",
+    world: "rpi",
+    imports: {
+        "wasi:gpio/delay.delay-ns": async | trappable,
+        "wasi:gpio/delay.delay-us": async | trappable,
+        "wasi:gpio/delay.delay-ms": async | trappable,
+        "wasi:gpio/delay.subscribe-ns": trappable,
+        "wasi:gpio/delay.subscribe-us": trappable,
+        "wasi:gpio/delay.subscribe-ms": trappable,
+        "wasi:gpio/delay.subscribe-deadline-ns": trappable,
+        "wasi:gpio/poll.poll": async | trappable,
+        "wasi:gpio/poll.[method]pollable.block": async | trappable,
+    },
+    with: {
+        "wasi:gpio/general": crate::wasi::gpio::general,
+        "wasi:gpio/digital": crate::wasi::gpio::digital,
+        "wasi:gpio/analog": crate::wasi::gpio::analog,
+        "wasi:gpio/counter": crate::wasi::gpio::counter,
+        "wasi:gpio/encoder": crate::wasi::gpio::encoder,
+        "wasi:gpio/port": crate::wasi::gpio::port,
+        "wasi:gpio/waveform": crate::wasi::gpio::waveform,
+        "wasi:gpio/stepper": crate::wasi::gpio::stepper,
+        "wasi:gpio/tone": crate::wasi::gpio::tone,
+        "wasi:gpio/watchdog": crate::wasi::gpio::watchdog,
+        "wasi:gpio/poll.pollable": wasmtime_wasi::p2::DynPollable,
+    }
+});
+
+use self::wasi::gpio::{delay, poll};
+use wasmtime_wasi::p2::bindings::io::poll as io;
+
+impl<T: WasiGpioView> delay::Host for GpioImpl<'_, T> {
+    async fn delay_ns(&mut self, ns: u64) -> wasmtime::Result<()> {
+        let ctx = self.ctx();
+        Ok(ctx
+            .delay
+            .delay_async(std::time::Duration::from_nanos(ns), &ctx.cancel)
+            .await?)
+    }
+
+    async fn delay_us(&mut self, us: u64) -> wasmtime::Result<()> {
+        let ctx = self.ctx();
+        Ok(ctx
+            .delay
+            .delay_async(std::time::Duration::from_micros(us), &ctx.cancel)
+            .await?)
+    }
+
+    async fn delay_ms(&mut self, ms: u64) -> wasmtime::Result<()> {
+        let ctx = self.ctx();
+        Ok(ctx
+            .delay
+            .delay_async(std::time::Duration::from_millis(ms), &ctx.cancel)
+            .await?)
+    }
+
+    fn subscribe_ns(&mut self, ns: u64) -> wasmtime::Result<Resource<PollableResource>> {
+        crate::wasi::gpio::delay::Host::subscribe_ns(self, ns)
+    }
+
+    fn subscribe_us(&mut self, us: u64) -> wasmtime::Result<Resource<PollableResource>> {
+        crate::wasi::gpio::delay::Host::subscribe_us(self, us)
+    }
+
+    fn subscribe_ms(&mut self, ms: u64) -> wasmtime::Result<Resource<PollableResource>> {
+        crate::wasi::gpio::delay::Host::subscribe_ms(self, ms)
+    }
+
+    fn now_ns(&mut self) -> u64 {
+        crate::wasi::gpio::delay::Host::now_ns(self)
+    }
+
+    fn subscribe_deadline_ns(
+        &mut self,
+        deadline_ns: u64,
+    ) -> wasmtime::Result<Resource<PollableResource>> {
+        self.subscribe_at(std::time::Duration::from_nanos(deadline_ns))
+    }
+}
+
+impl<T: WasiGpioView> poll::Host for GpioImpl<'_, T> {
+    async fn poll(&mut self, in_: Vec<Resource<PollableResource>>) -> wasmtime::Result<Vec<u32>> {
+        if in_.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let cancel = self.ctx().cancel.clone();
+        cancellable(io::Host::poll(self.table(), in_), &cancel).await?
+    }
+}
+
+impl<T: WasiGpioView> poll::HostPollable for GpioImpl<'_, T> {
+    fn ready(&mut self, self_: Resource<PollableResource>) -> bool {
+        crate::wasi::gpio::poll::HostPollable::ready(self, self_)
+    }
+
+    async fn block(&mut self, self_: Resource<PollableResource>) -> wasmtime::Result<()> {
+        let cancel = self.ctx().cancel.clone();
+        cancellable(io::HostPollable::block(self.table(), self_), &cancel).await?
+    }
+
+    fn drop(&mut self, rep: Resource<PollableResource>) -> wasmtime::Result<()> {
+        io::HostPollable::drop(self.table(), rep)
+    }
+}
+
+/// Async variant of the `wasi:io/poll` bindings that replace the ones of wasmtime-wasi
+pub mod io_poll {
+    wasmtime::component::bindgen!({
+        path: "../wit-io/deps/io",
+        world: "wasi:io/imports",
+        imports: {
+            "wasi:io/poll.poll": async | trappable,
+            "wasi:io/poll.[method]pollable.block": async | trappable,
+        },
+        with: {
+            "wasi:io/error": wasmtime_wasi::p2::bindings::io::error,
+            "wasi:io/streams": wasmtime_wasi::p2::bindings::io::streams,
+            "wasi:io/poll.pollable": wasmtime_wasi::p2::DynPollable,
+        }
+    });
+}
+
+impl<T: WasiGpioView> io_poll::wasi::io::poll::Host for GpioImpl<'_, T> {
+    async fn poll(&mut self, in_: Vec<Resource<PollableResource>>) -> wasmtime::Result<Vec<u32>> {
+        // wasi:io traps on an empty list, unlike the legacy interface
+        if in_.is_empty() {
+            anyhow::bail!("empty poll list");
+        }
+
+        poll::Host::poll(self, in_).await
+    }
+}
+
+impl<T: WasiGpioView> io_poll::wasi::io::poll::HostPollable for GpioImpl<'_, T> {
+    fn ready(&mut self, self_: Resource<PollableResource>) -> bool {
+        poll::HostPollable::ready(self, self_)
+    }
+
+    async fn block(&mut self, self_: Resource<PollableResource>) -> wasmtime::Result<()> {
+        poll::HostPollable::block(self, self_).await
+    }
+
+    fn drop(&mut self, rep: Resource<PollableResource>) -> wasmtime::Result<()> {
+        poll::HostPollable::drop(self, rep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io_bindings::tests::TestHost;
+    use std::time::{Duration, Instant};
+    use wasmtime::component::{Component, Linker};
+    use wasmtime::{Config, Engine, Store};
+
+    /// Component built against `wit` that waits with `delay-ms` and then blocks on a pollable of `subscribe-ms`
+    ///
+    /// Its `run` export takes the milliseconds to wait each time
+    const COMPONENT: &str = r#"
+        (component $c
+            (import "wasi:gpio/poll@0.2.1" (instance $poll
+                (export "pollable" (type $pollable (sub resource)))
+                (export "[method]pollable.block" (func (param "self" (borrow $pollable))))
+            ))
+            (alias export $poll "pollable" (type $pollable))
+            (import "wasi:gpio/delay@0.2.1" (instance $delay
+                (alias outer $c $pollable (type $p))
+                (export "pollable" (type $pollable (eq $p)))
+                (export "delay-ms" (func (param "ms" u64)))
+                (export "subscribe-ms" (func (param "ms" u64) (result (own $pollable))))
+            ))
+
+            (core func $delay-ms (canon lower (func $delay "delay-ms")))
+            (core func $subscribe-ms (canon lower (func $delay "subscribe-ms")))
+            (core func $block (canon lower (func $poll "[method]pollable.block")))
+
+            (core module $m
+                (import "delay" "delay-ms" (func $delay-ms (param i64)))
+                (import "delay" "subscribe-ms" (func $subscribe-ms (param i64) (result i32)))
+                (import "poll" "block" (func $block (param i32)))
+                (func (export "run") (param i64)
+                    (call $delay-ms (local.get 0))
+                    (call $block (call $subscribe-ms (local.get 0))))
+            )
+            (core instance $i (instantiate $m
+                (with "delay" (instance
+                    (export "delay-ms" (func $delay-ms))
+                    (export "subscribe-ms" (func $subscribe-ms))))
+                (with "poll" (instance (export "block" (func $block))))
+            ))
+
+            (func (export "run") (param "ms" u64) (canon lift (core func $i "run")))
+        )
+    "#;
+
+    /// Runs the `run` export of `COMPONENT` on an async engine, after cancelling the store when `cancel` is set
+    fn run(ms: u64, cancel: bool) -> wasmtime::Result<()> {
+        let mut config = Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let mut linker = Linker::new(&engine);
+        wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
+        crate::add_to_linker_async(&mut linker)?;
+
+        let component = Component::new(&engine, COMPONENT)?;
+        let mut store = Store::new(&engine, TestHost::new());
+        if cancel {
+            store.data().gpio_ctx.cancel.cancel();
+        }
+
+        wasmtime_wasi::runtime::in_tokio(async {
+            let instance = linker.instantiate_async(&mut store, &component).await?;
+            let run = instance.get_typed_func::<(u64,), ()>(&mut store, "run")?;
+            run.call_async(&mut store, (ms,)).await
+        })
+    }
+
+    #[test]
+    fn awaits_delays_and_blocks() {
+        let start = Instant::now();
+
+        run(20, false).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn cancelling_stops_an_awaited_delay() {
+        let start = Instant::now();
+
+        assert!(run(60_000, true).is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,292 @@
+use anyhow::Context;
+
+/// A GPIO pin on the header of a board
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct BoardPin {
+    /// BCM number, the `n` in a `GPIOn` physical label
+    pub bcm: u8,
+    /// Position on the header, the `n` in a `PINn` physical label, none for a GPIO that is not on the header
+    #[serde(default)]
+    pub header: Option<u8>,
+    /// Alternate names the pin can be referred to by, like the function printed on a wiring diagram
+    #[serde(default)]
+    pub names: Vec<String>,
+    /// Bus the pin is reserved for when that bus is enabled
+    #[serde(default)]
+    pub reserved: Option<String>,
+    /// Hardware PWM channel that can be routed to the pin
+    #[serde(default)]
+    pub pwm: Option<u8>,
+    /// Whether the pin can be read by an ADC
+    #[serde(default)]
+    pub adc: bool,
+    /// Whether the pin has configurable pull resistors
+    #[serde(default = "default_pull")]
+    pub pull: bool,
+}
+
+fn default_pull() -> bool {
+    true
+}
+
+/// Description of the pins a board exposes, custom ones are read from a TOML file
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Board {
+    pub name: String,
+    #[serde(rename = "pin")]
+    pub pins: Vec<BoardPin>,
+}
+
+/// Names of the boards that do not need a description file
+pub const BUILTIN_BOARDS: [&str; 5] = ["rpi3", "rpi4", "rpi5", "zero", "cm4"];
+
+/// (BCM number, header position, alternate names) of every GPIO on the 40 pin Raspberry Pi header
+const RASPBERRY_PI_HEADER: [(u8, u8, &[&str]); 28] = [
+    (0, 27, &["ID_SD"]),
+    (1, 28, &["ID_SC"]),
+    (2, 3, &["SDA1"]),
+    (3, 5, &["SCL1"]),
+    (4, 7, &["GPCLK0"]),
+    (5, 29, &[]),
+    (6, 31, &[]),
+    (7, 26, &["CE1"]),
+    (8, 24, &["CE0"]),
+    (9, 21, &["MISO"]),
+    (10, 19, &["MOSI"]),
+    (11, 23, &["SCLK"]),
+    (12, 32, &["PWM0"]),
+    (13, 33, &["PWM1"]),
+    (14, 8, &["TXD"]),
+    (15, 10, &["RXD"]),
+    (16, 36, &[]),
+    (17, 11, &[]),
+    (18, 12, &["PCM_CLK"]),
+    (19, 35, &["PCM_FS"]),
+    (20, 38, &["PCM_DIN"]),
+    (21, 40, &["PCM_DOUT"]),
+    (22, 15, &[]),
+    (23, 16, &[]),
+    (24, 18, &[]),
+    (25, 22, &[]),
+    (26, 37, &[]),
+    (27, 13, &[]),
+];
+
+impl Board {
+    /// The 40 pin header shared by every Raspberry Pi since the B+, `rp1` selects the PWM channels of the Pi 5
+    fn raspberry_pi_header(name: &str, rp1: bool) -> Self {
+        let pins = RASPBERRY_PI_HEADER
+            .iter()
+            .map(|&(bcm, header, names)| BoardPin {
+                bcm,
+                header: Some(header),
+                names: names.iter().map(|name| name.to_string()).collect(),
+                reserved: match bcm {
+                    0 | 1 => Some("ID EEPROM (I2C0)".to_string()),
+                    2 | 3 => Some("I2C1".to_string()),
+                    7..=11 => Some("SPI0".to_string()),
+                    14 | 15 => Some("UART0".to_string()),
+                    _ => None,
+                },
+                pwm: match (bcm, rp1) {
+                    (12, _) => Some(0),
+                    (13, _) => Some(1),
+                    (18, false) => Some(0),
+                    (19, false) => Some(1),
+                    (18, true) => Some(2),
+                    (19, true) => Some(3),
+                    _ => None,
+                },
+                adc: false,
+                pull: true,
+            })
+            .collect();
+
+        Self {
+            name: name.to_string(),
+            pins,
+        }
+    }
+
+    /// The 40 pin header with the PWM channels of the boards before the Pi 5
+    pub fn raspberry_pi() -> Self {
+        Self::raspberry_pi_header("Raspberry Pi", false)
+    }
+
+    /// Returns one of the `BUILTIN_BOARDS`
+    pub fn builtin(name: &str) -> Option<Self> {
+        let board = match name {
+            "rpi3" => Self::raspberry_pi_header("Raspberry Pi 3", false),
+            "rpi4" => Self::raspberry_pi_header("Raspberry Pi 4", false),
+            "rpi5" => Self::raspberry_pi_header("Raspberry Pi 5", true),
+            "zero" => Self::raspberry_pi_header("Raspberry Pi Zero", false),
+            // The module only brings out GPIO 0 to 27, the IO board puts them on the 40 pin header
+            "cm4" => Self::raspberry_pi_header("Raspberry Pi Compute Module 4", false),
+            _ => return None,
+        };
+
+        Some(board)
+    }
+
+    /// Reads a board description file
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read board file `{path}`"))?;
+
+        toml::from_str(&source).with_context(|| format!("failed to parse board file `{path}`"))
+    }
+
+    /// Returns the builtin board with the given name, otherwise reads it as a board description file, detects the board when not given
+    pub fn from_arg(board: Option<&str>) -> anyhow::Result<Self> {
+        let Some(board) = board else {
+            return Ok(Self::detect());
+        };
+
+        match Self::builtin(board) {
+            Some(board) => Ok(board),
+            None => Self::load(board),
+        }
+    }
+
+    /// Returns the builtin board matching the Raspberry Pi the host runs on, the generic header when unknown
+    pub fn detect() -> Self {
+        use rppal::system::Model;
+
+        let name = match rppal::system::DeviceInfo::new().map(|info| info.model()) {
+            Ok(
+                Model::RaspberryPi3APlus
+                | Model::RaspberryPi3B
+                | Model::RaspberryPi3BPlus
+                | Model::RaspberryPiComputeModule3
+                | Model::RaspberryPiComputeModule3Plus,
+            ) => "rpi3",
+            Ok(Model::RaspberryPi4B | Model::RaspberryPi400) => "rpi4",
+            Ok(
+                Model::RaspberryPi5
+                | Model::RaspberryPi500
+                | Model::RaspberryPiComputeModule5
+                | Model::RaspberryPiComputeModule5Lite,
+            ) => "rpi5",
+            Ok(Model::RaspberryPiZero | Model::RaspberryPiZeroW | Model::RaspberryPiZero2W) => {
+                "zero"
+            }
+            Ok(Model::RaspberryPiComputeModule4 | Model::RaspberryPiComputeModule4S) => "cm4",
+            _ => return Self::raspberry_pi(),
+        };
+
+        Self::builtin(name).unwrap_or_else(Self::raspberry_pi)
+    }
+
+    pub fn find(&self, bcm: u8) -> Option<&BoardPin> {
+        self.pins.iter().find(|pin| pin.bcm == bcm)
+    }
+
+    /// Returns the pin a physical label refers to, as `GPIOn`, `BCMn`, `PINn` or one of its alternate names
+    pub fn resolve(&self, plabel: &str) -> Option<&BoardPin> {
+        let plabel = plabel.to_ascii_uppercase();
+        let number = |prefix| {
+            plabel
+                .strip_prefix(prefix)
+                .and_then(|number: &str| number.parse::<u8>().ok())
+        };
+
+        if let Some(bcm) = number("GPIO").or_else(|| number("BCM")) {
+            return self.find(bcm);
+        }
+
+        if let Some(header) = number("PIN") {
+            return self.pins.iter().find(|pin| pin.header == Some(header));
+        }
+
+        self.pins.iter().find(|pin| {
+            pin.names
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(&plabel))
+        })
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::raspberry_pi()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bcm(board: &Board, plabel: &str) -> Option<u8> {
+        board.resolve(plabel).map(|pin| pin.bcm)
+    }
+
+    #[test]
+    fn resolves_bcm_numbers_header_positions_and_names() {
+        let board = Board::raspberry_pi();
+
+        assert_eq!(bcm(&board, "GPIO17"), Some(17));
+        assert_eq!(bcm(&board, "BCM17"), Some(17));
+        assert_eq!(bcm(&board, "PIN11"), Some(17));
+        assert_eq!(bcm(&board, "SDA1"), Some(2));
+        assert_eq!(bcm(&board, "PIN27"), Some(0));
+        assert_eq!(bcm(&board, "GPIO0"), Some(0));
+    }
+
+    #[test]
+    fn resolves_case_insensitively() {
+        let board = Board::raspberry_pi();
+
+        assert_eq!(bcm(&board, "gpio17"), Some(17));
+        assert_eq!(bcm(&board, "Pin11"), Some(17));
+        assert_eq!(bcm(&board, "pcm_clk"), Some(18));
+    }
+
+    #[test]
+    fn refuses_labels_that_name_no_pin() {
+        let board = Board::raspberry_pi();
+
+        for plabel in [
+            "", "GPIO", "GPIO28", "GPIO99", "GPIO256", "GPIO-1", "PIN1", "PIN41", "PINx",
+            "GPIO 17", "LED",
+        ] {
+            assert_eq!(bcm(&board, plabel), None, "{plabel}");
+        }
+    }
+
+    #[test]
+    fn resolves_pins_without_a_header_position() {
+        let board: Board = toml::from_str(
+            r#"
+            name = "Carrier"
+
+            [[pin]]
+            bcm = 40
+            pwm = 0
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(bcm(&board, "GPIO40"), Some(40));
+        assert_eq!(bcm(&board, "PIN0"), None);
+    }
+
+    #[test]
+    fn routes_the_pwm_channels_of_the_board() {
+        let pwm = |board: &Board, plabel| board.resolve(plabel).and_then(|pin| pin.pwm);
+        let rpi4 = Board::builtin("rpi4").unwrap();
+        let rpi5 = Board::builtin("rpi5").unwrap();
+
+        assert_eq!(pwm(&rpi4, "GPIO18"), Some(0));
+        assert_eq!(pwm(&rpi5, "GPIO18"), Some(2));
+        assert_eq!(pwm(&rpi4, "GPIO17"), None);
+    }
+
+    #[test]
+    fn knows_every_builtin_board() {
+        for name in BUILTIN_BOARDS {
+            assert!(Board::builtin(name).is_some(), "{name}");
+        }
+
+        assert!(Board::builtin("rpi2").is_none());
+    }
+}
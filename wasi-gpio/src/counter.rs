@@ -0,0 +1,98 @@
+use crate::ctx::WasiGpioView;
+use crate::digital::DigitalInPin;
+use crate::impls::GpioImpl;
+use crate::util::Shared;
+use crate::wasi::gpio::{counter, general};
+use crate::{poll, watch_event};
+use wasmtime::component::Resource;
+
+pub struct EdgeCounter {
+    pub count: Shared<watch_event::EdgeCount>,
+}
+
+impl EdgeCounter {
+    pub fn new(count: Shared<watch_event::EdgeCount>) -> Self {
+        Self { count }
+    }
+}
+
+impl<'a, T: WasiGpioView> counter::Host for GpioImpl<'a, T> {}
+
+impl<'a, T: WasiGpioView> counter::HostEdgeCounter for GpioImpl<'a, T> {
+    fn get(
+        &mut self,
+        pin: Resource<DigitalInPin>,
+        edge: counter::Edge,
+        debounce_us: u64,
+    ) -> Result<Resource<EdgeCounter>, general::GpioError> {
+        let pin = self
+            .table()
+            .get(&pin)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .clone();
+
+        let watch_type = match edge {
+            counter::Edge::Rising => watch_event::WatchType::Rising,
+            counter::Edge::Falling => watch_event::WatchType::Falling,
+            counter::Edge::Both => watch_event::WatchType::AnyEdge,
+        };
+
+        let watch_type = match &pin.config.active_level {
+            general::ActiveLevel::ActiveHigh => watch_type,
+            general::ActiveLevel::ActiveLow => !watch_type,
+        };
+
+        let count = self.ctx().watcher.watch_edges(
+            &pin,
+            watch_type,
+            std::time::Duration::from_micros(debounce_us),
+        );
+
+        self.table()
+            .push(EdgeCounter::new(count))
+            .map_err(|err| general::GpioError::Other(err.to_string()))
+    }
+
+    fn count(&mut self, self_: Resource<EdgeCounter>) -> Result<u64, general::GpioError> {
+        let counter = self
+            .table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?;
+
+        Ok(counter.count.lock().unwrap().count())
+    }
+
+    fn reset(&mut self, self_: Resource<EdgeCounter>) -> Result<(), general::GpioError> {
+        let counter = self
+            .table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?;
+
+        counter.count.lock().unwrap().reset();
+        Ok(())
+    }
+
+    fn watch_count(
+        &mut self,
+        self_: Resource<EdgeCounter>,
+        threshold: u64,
+    ) -> Result<Resource<poll::PollableResource>, general::GpioError> {
+        let trigger = self
+            .table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .count
+            .lock()
+            .unwrap()
+            .watch_count(threshold);
+
+        self.push_pollable(poll::Pollable::new(trigger))
+            .map_err(|err| general::GpioError::Other(err.to_string()))
+    }
+
+    fn drop(&mut self, rep: Resource<EdgeCounter>) -> wasmtime::Result<()> {
+        let counter = self.table().delete(rep).expect("failed to delete resource");
+        counter.count.lock().unwrap().stop();
+        Ok(())
+    }
+}
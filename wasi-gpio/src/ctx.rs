@@ -2,20 +2,39 @@ use std::marker::PhantomData;
 use wasmtime::component::HasData;
 use wasmtime_wasi::{ResourceTable, WasiView};
 
+use crate::delay::Delay;
 use crate::impls::GpioImpl;
+use crate::interlock::Interlocks;
 use crate::policies::Policies;
+use crate::rate_limit::RateLimiters;
+use crate::util::CancelToken;
 use crate::watch_event::Watcher;
+use crate::watchdog::WatchdogTimer;
 
 pub struct WasiGpioCtx {
     pub policies: Policies,
     pub watcher: Watcher,
+    pub delay: Delay,
+    pub rate_limiters: RateLimiters,
+    pub interlocks: Interlocks,
+    /// Watchdog of the policy, the host starts it with `WatchdogTimer::watch` before running the component
+    pub watchdog: Option<WatchdogTimer>,
+    /// Cancels blocking delays and pollables, clone it before handing the context to a store
+    pub cancel: CancelToken,
 }
 
 impl WasiGpioCtx {
     pub fn new(policies: Policies) -> Self {
         Self {
+            interlocks: Interlocks::new(&policies),
+            watchdog: policies.wasi.watchdog.map(|watchdog| {
+                WatchdogTimer::new(std::time::Duration::from_millis(watchdog.interval_ms))
+            }),
             policies,
             watcher: Watcher::new(),
+            delay: Delay::default(),
+            rate_limiters: RateLimiters::default(),
+            cancel: CancelToken::new(),
         }
     }
 }
@@ -1,22 +1,301 @@
 use crate::ctx::WasiGpioView;
 use crate::impls::GpioImpl;
+use crate::util::CANCEL_CHECK_INTERVAL;
 use crate::wasi::gpio as bindings;
+use crate::{poll, util};
+use wasmtime::component::Resource;
 
-pub struct Delay {}
+/// Waits shorter than this get spun instead of handed to the OS scheduler, which often overshoots them
+pub const DEFAULT_SPIN_THRESHOLD: std::time::Duration = std::time::Duration::from_micros(200);
+
+/// Waits until `deadline`, sleeping with `sleep` in steps of at most `CANCEL_CHECK_INTERVAL` and spinning for the last `spin_threshold`
+///
+/// Every wait goes through this loop, a deadline of `None` is too far to be represented and only ends by getting cancelled
+async fn wait<F: std::future::Future<Output = ()>>(
+    deadline: Option<std::time::Instant>,
+    spin_threshold: std::time::Duration,
+    cancel: &util::CancelToken,
+    mut sleep: impl FnMut(std::time::Duration) -> F,
+) -> Result<(), util::Cancelled> {
+    loop {
+        cancel.check()?;
+
+        let remaining = match deadline {
+            Some(deadline) => deadline.saturating_duration_since(std::time::Instant::now()),
+            None => std::time::Duration::MAX,
+        };
+
+        if remaining <= spin_threshold {
+            break;
+        }
+
+        sleep((remaining - spin_threshold).min(CANCEL_CHECK_INTERVAL)).await;
+    }
+
+    if let Some(deadline) = deadline {
+        while std::time::Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs `wait` on the calling thread, sleeping it with `std::thread::sleep`
+fn wait_blocking(
+    deadline: Option<std::time::Instant>,
+    spin_threshold: std::time::Duration,
+    cancel: &util::CancelToken,
+) -> Result<(), util::Cancelled> {
+    let wait = std::pin::pin!(wait(deadline, spin_threshold, cancel, |duration| {
+        std::thread::sleep(duration);
+        std::future::ready(())
+    }));
+
+    // The sleeps finish before they are awaited, so the wait is done after a single poll
+    wasmtime_wasi::runtime::poll_noop(wait).expect("blocking wait did not finish")
+}
+
+/// Blocks the calling thread until `deadline`, sleeping for the bulk of the time and spinning for the last `spin_threshold`
+pub fn wait_until(deadline: std::time::Instant, spin_threshold: std::time::Duration) {
+    let _ = wait_blocking(Some(deadline), spin_threshold, &util::CancelToken::new());
+}
+
+/// Same as `wait_until`, returns early with `Err(Cancelled)` once `cancel` gets cancelled
+pub fn wait_until_cancellable(
+    deadline: std::time::Instant,
+    spin_threshold: std::time::Duration,
+    cancel: &util::CancelToken,
+) -> Result<(), util::Cancelled> {
+    wait_blocking(Some(deadline), spin_threshold, cancel)
+}
+
+/// Accuracy of the delays the component asked for, the error is the time a delay took longer than requested
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DelayStats {
+    pub count: u64,
+    pub total_error: std::time::Duration,
+    pub max_error: std::time::Duration,
+}
+
+impl DelayStats {
+    pub fn mean_error(&self) -> std::time::Duration {
+        match self.count {
+            0 => std::time::Duration::ZERO,
+            count => self.total_error / count.min(u32::MAX as u64) as u32,
+        }
+    }
+
+    fn record(&mut self, error: std::time::Duration) {
+        self.count += 1;
+        self.total_error += error;
+        self.max_error = self.max_error.max(error);
+    }
+}
+
+pub struct Delay {
+    pub spin_threshold: std::time::Duration,
+    /// Moves the thread running the component to realtime scheduling on its first delay
+    pub realtime: bool,
+    realtime_applied: bool,
+    stats: DelayStats,
+    /// Starting point of the clock the component sees
+    epoch: std::time::Instant,
+}
+
+impl Delay {
+    pub fn new(spin_threshold: std::time::Duration, realtime: bool) -> Self {
+        Self {
+            spin_threshold,
+            realtime,
+            realtime_applied: false,
+            stats: DelayStats::default(),
+            epoch: std::time::Instant::now(),
+        }
+    }
+
+    /// Returns the time since the starting point of the clock
+    pub fn now(&self) -> std::time::Duration {
+        self.epoch.elapsed()
+    }
+
+    /// Returns the moment the clock reaches `time`, saturating far in the future
+    pub fn instant_at(&self, time: std::time::Duration) -> std::time::Instant {
+        self.epoch
+            .checked_add(time)
+            .unwrap_or_else(|| self.epoch + std::time::Duration::from_secs(u32::MAX as u64))
+    }
+
+    pub fn stats(&self) -> DelayStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = DelayStats::default();
+    }
+
+    fn apply_realtime(&mut self) {
+        if self.realtime && !self.realtime_applied {
+            // Delays are still correct without realtime scheduling, only less precise
+            let _ = util::set_realtime_priority();
+            self.realtime_applied = true;
+        }
+    }
+
+    /// Blocks for `duration`, returns early with `Err(Cancelled)` once `cancel` gets cancelled
+    pub fn delay(
+        &mut self,
+        duration: std::time::Duration,
+        cancel: &util::CancelToken,
+    ) -> Result<(), util::Cancelled> {
+        self.apply_realtime();
+
+        let start = std::time::Instant::now();
+        wait_blocking(start.checked_add(duration), self.spin_threshold, cancel)?;
+
+        self.stats.record(start.elapsed().saturating_sub(duration));
+        Ok(())
+    }
+
+    /// Async variant of `delay`, only the last `spin_threshold` of the delay blocks the thread
+    pub async fn delay_async(
+        &mut self,
+        duration: std::time::Duration,
+        cancel: &util::CancelToken,
+    ) -> Result<(), util::Cancelled> {
+        self.apply_realtime();
+
+        let start = std::time::Instant::now();
+        wait(
+            start.checked_add(duration),
+            self.spin_threshold,
+            cancel,
+            tokio::time::sleep,
+        )
+        .await?;
+
+        self.stats.record(start.elapsed().saturating_sub(duration));
+        Ok(())
+    }
+}
+
+impl Default for Delay {
+    fn default() -> Self {
+        Self::new(DEFAULT_SPIN_THRESHOLD, false)
+    }
+}
 
 // Implement the top-level Host trait directly
 impl<T: WasiGpioView> bindings::delay::Host for GpioImpl<'_, T> {
-    fn delay_ns(&mut self, ns: u64) -> () {
-        std::thread::sleep(std::time::Duration::from_nanos(ns));
+    fn delay_ns(&mut self, ns: u64) -> wasmtime::Result<()> {
+        let ctx = self.ctx();
+        Ok(ctx
+            .delay
+            .delay(std::time::Duration::from_nanos(ns), &ctx.cancel)?)
+    }
+
+    fn delay_us(&mut self, us: u64) -> wasmtime::Result<()> {
+        let ctx = self.ctx();
+        Ok(ctx
+            .delay
+            .delay(std::time::Duration::from_micros(us), &ctx.cancel)?)
+    }
+
+    fn delay_ms(&mut self, ms: u64) -> wasmtime::Result<()> {
+        let ctx = self.ctx();
+        Ok(ctx
+            .delay
+            .delay(std::time::Duration::from_millis(ms), &ctx.cancel)?)
+    }
+
+    fn subscribe_ns(&mut self, ns: u64) -> wasmtime::Result<Resource<poll::PollableResource>> {
+        let deadline = self
+            .ctx()
+            .delay
+            .now()
+            .saturating_add(std::time::Duration::from_nanos(ns));
+        self.subscribe_at(deadline)
+    }
+
+    fn subscribe_us(&mut self, us: u64) -> wasmtime::Result<Resource<poll::PollableResource>> {
+        let deadline = self
+            .ctx()
+            .delay
+            .now()
+            .saturating_add(std::time::Duration::from_micros(us));
+        self.subscribe_at(deadline)
     }
 
-    fn delay_us(&mut self, us: u64) -> () {
-        std::thread::sleep(std::time::Duration::from_micros(us));
+    fn subscribe_ms(&mut self, ms: u64) -> wasmtime::Result<Resource<poll::PollableResource>> {
+        let deadline = self
+            .ctx()
+            .delay
+            .now()
+            .saturating_add(std::time::Duration::from_millis(ms));
+        self.subscribe_at(deadline)
     }
 
-    fn delay_ms(&mut self, ms: u64) -> () {
-        std::thread::sleep(std::time::Duration::from_millis(ms));
+    fn now_ns(&mut self) -> u64 {
+        self.ctx().delay.now().as_nanos() as u64
+    }
+
+    fn subscribe_deadline_ns(
+        &mut self,
+        deadline_ns: u64,
+    ) -> wasmtime::Result<Resource<poll::PollableResource>> {
+        self.subscribe_at(std::time::Duration::from_nanos(deadline_ns))
     }
 }
 
-// Remove the HostDelay implementation since the resource is gone
+impl<T: WasiGpioView> GpioImpl<'_, T> {
+    pub(crate) fn subscribe_at(
+        &mut self,
+        time: std::time::Duration,
+    ) -> wasmtime::Result<Resource<poll::PollableResource>> {
+        let deadline = self.ctx().delay.instant_at(time);
+
+        self.push_pollable(poll::Pollable::with_deadline(deadline))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn waits_until_the_deadline() {
+        let deadline = Instant::now() + Duration::from_millis(20);
+        wait_until(deadline, DEFAULT_SPIN_THRESHOLD);
+
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[test]
+    fn cancelled_wait_returns_early() {
+        let cancel = util::CancelToken::new();
+        cancel.cancel();
+
+        let start = Instant::now();
+        let result = wait_until_cancellable(
+            start + Duration::from_secs(10),
+            DEFAULT_SPIN_THRESHOLD,
+            &cancel,
+        );
+
+        assert!(result.is_err());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn delays_are_recorded_in_the_stats() {
+        let mut delay = Delay::default();
+        let cancel = util::CancelToken::new();
+
+        delay.delay(Duration::from_millis(5), &cancel).unwrap();
+        wasmtime_wasi::runtime::in_tokio(delay.delay_async(Duration::from_millis(5), &cancel))
+            .unwrap();
+
+        assert_eq!(delay.stats().count, 2);
+    }
+}
@@ -1,4 +1,5 @@
 use super::{DigitalConfigBuilder, DigitalInOutPin, DigitalInPin, DigitalOutPin};
+use crate::policies;
 use crate::util::Shared;
 use crate::wasi::gpio::{digital, general};
 
@@ -15,6 +16,49 @@ pub fn check_invalid_flags(
     Ok(())
 }
 
+/// Returns the level that has to be written to the physical pin to reach `pin_state`
+pub fn level_for(
+    active_level: general::ActiveLevel,
+    pin_state: digital::PinState,
+) -> rppal::gpio::Level {
+    let pin_state = match active_level {
+        general::ActiveLevel::ActiveHigh => pin_state,
+        general::ActiveLevel::ActiveLow => !pin_state,
+    };
+
+    pin_state.into()
+}
+
+/// Returns the state an output starts in from its flags, none when it is left at its current level
+pub fn initial_state(flags: &[digital::DigitalFlag]) -> Option<digital::PinState> {
+    let mut pin_state = None;
+    for flag in flags.iter() {
+        if *flag == digital::DigitalFlag::ACTIVE {
+            pin_state = Some(digital::PinState::Active);
+        } else if *flag == digital::DigitalFlag::INACTIVE {
+            pin_state = Some(digital::PinState::Inactive);
+        }
+    }
+
+    pin_state
+}
+
+/// Returns the state a physical pin at `level` is in
+pub fn state_for(
+    active_level: general::ActiveLevel,
+    level: rppal::gpio::Level,
+) -> digital::PinState {
+    let pin_state = match level {
+        rppal::gpio::Level::Low => digital::PinState::Inactive,
+        rppal::gpio::Level::High => digital::PinState::Active,
+    };
+
+    match active_level {
+        general::ActiveLevel::ActiveHigh => pin_state,
+        general::ActiveLevel::ActiveLow => !pin_state,
+    }
+}
+
 impl DigitalOutPin {
     pub fn new(
         pin: rppal::gpio::Pin,
@@ -35,22 +79,44 @@ impl DigitalOutPin {
             }
         };
 
-        Self { pin, config }
+        Self {
+            pin: std::sync::Arc::new(std::sync::Mutex::new(pin)),
+            config,
+        }
     }
 
     pub fn get_config(&self) -> &digital::DigitalConfig {
         &self.config
     }
 
+    /// Leaves the pin as it is when dropped instead of resetting it, for pins held by their safe state
+    pub fn keep_state(&mut self) {
+        self.pin.lock().unwrap().set_reset_on_drop(false);
+    }
+
     pub fn write(&mut self, pin_state: digital::PinState) {
-        let pin_state = match &self.config.active_level {
-            general::ActiveLevel::ActiveHigh => pin_state,
-            general::ActiveLevel::ActiveLow => !pin_state,
+        let level = self.get_level(pin_state);
+
+        (*self.pin.lock().unwrap()).write(level);
+    }
+
+    /// Returns true when writing `pin_state` changes the level of the physical pin
+    pub fn changes(&self, pin_state: digital::PinState) -> bool {
+        let level = match self.pin.lock().unwrap().is_set_high() {
+            true => rppal::gpio::Level::High,
+            false => rppal::gpio::Level::Low,
         };
 
-        let pin_state: rppal::gpio::Level = pin_state.into();
+        self.get_level(pin_state) != level
+    }
+
+    /// Returns the level that has to be written to the physical pin to reach `pin_state`
+    pub fn get_level(&self, pin_state: digital::PinState) -> rppal::gpio::Level {
+        level_for(self.config.active_level, pin_state)
+    }
 
-        self.pin.write(pin_state);
+    pub fn clone_pin(&self) -> Shared<rppal::gpio::OutputPin> {
+        self.pin.clone()
     }
 }
 
@@ -99,51 +165,72 @@ impl DigitalInPin {
 }
 
 impl DigitalInOutPin {
+    /// Creates the pin, an output starts at `start_level` when given instead of the level the pin was left at
     pub fn new(
         pin: rppal::gpio::Pin,
         config: digital::DigitalConfig,
         pin_mode: digital::PinMode,
+        start_level: Option<rppal::gpio::Level>,
     ) -> Self {
-        match pin_mode {
-            general::PinMode::In => Self {
-                pin: pin.into_io(rppal::gpio::Mode::Input),
-                config,
-            },
-            general::PinMode::Out => Self {
-                pin: pin.into_io(rppal::gpio::Mode::Output),
-                config,
-            },
+        let mut pin = Self {
+            pin: pin.into_io(rppal::gpio::Mode::Input),
+            config,
+        };
+
+        if pin_mode == general::PinMode::Out {
+            pin.set_pin_mode(general::PinMode::Out, start_level);
         }
+
+        pin
+    }
+
+    /// Leaves the pin as it is when dropped instead of resetting it, for pins held by their safe state
+    pub fn keep_state(&mut self) {
+        self.pin.set_reset_on_drop(false);
+    }
+
+    pub fn is_output(&self) -> bool {
+        self.pin.mode() == rppal::gpio::Mode::Output
+    }
+
+    /// Returns the level that has to be written to the physical pin to reach `pin_state`
+    pub fn get_level(&self, pin_state: digital::PinState) -> rppal::gpio::Level {
+        level_for(self.config.active_level, pin_state)
     }
 
     pub fn get_config(&self) -> &digital::DigitalConfig {
         &self.config
     }
 
-    pub fn write(&mut self, pin_state: digital::PinState) {
-        let pin_state = match &self.config.active_level {
-            general::ActiveLevel::ActiveHigh => pin_state,
-            general::ActiveLevel::ActiveLow => !pin_state,
-        };
+    /// Returns true when writing `pin_state` changes the level of the physical pin
+    pub fn changes(&self, pin_state: digital::PinState) -> bool {
+        self.read() != pin_state
+    }
 
-        let pin_state: rppal::gpio::Level = pin_state.into();
+    pub fn write(&mut self, pin_state: digital::PinState) {
+        let level = self.get_level(pin_state);
 
-        self.pin.write(pin_state);
+        self.pin.write(level);
     }
 
     pub fn read(&self) -> digital::PinState {
-        let pin_state = match self.pin.read() {
-            rppal::gpio::Level::Low => digital::PinState::Inactive,
-            rppal::gpio::Level::High => digital::PinState::Active,
-        };
+        state_for(self.config.active_level, self.pin.read())
+    }
 
-        match self.config.active_level {
-            general::ActiveLevel::ActiveHigh => pin_state,
-            general::ActiveLevel::ActiveLow => !pin_state,
+    /// Switches the mode, an input that becomes an output is driven to `start_level` first when given
+    pub fn set_pin_mode(
+        &mut self,
+        mode: general::PinMode,
+        start_level: Option<rppal::gpio::Level>,
+    ) {
+        if let Some(start_level) = start_level
+            && mode == general::PinMode::Out
+            && !self.is_output()
+        {
+            // Sets the output register while the pin still floats, so it never drives the old level
+            self.pin.write(start_level);
         }
-    }
 
-    pub fn set_pin_mode(&mut self, mode: general::PinMode) {
         self.pin.set_mode(mode.into());
     }
 }
@@ -184,9 +271,17 @@ impl DigitalConfigBuilder {
             pin_mode,
             active_level: None,
             pull_resistor: None,
+            flags: Vec::new(),
+            constraints: policies::PinConstraints::default(),
         }
     }
 
+    /// Applies the constraints the policy puts on the pin
+    pub fn constrain(mut self, constraints: policies::PinConstraints) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
     fn add_active_level(&mut self, active_level: general::ActiveLevel) {
         self.active_level = Some(active_level);
     }
@@ -196,6 +291,8 @@ impl DigitalConfigBuilder {
     }
 
     pub fn add_flags(mut self, flags: Vec<digital::DigitalFlag>) -> Self {
+        self.flags.extend(flags.iter().copied());
+
         for flag in flags {
             if flag == digital::DigitalFlag::ACTIVE_HIGH {
                 self.add_active_level(general::ActiveLevel::ActiveHigh);
@@ -212,9 +309,20 @@ impl DigitalConfigBuilder {
     }
 
     pub fn build(self) -> Result<digital::DigitalConfig, general::GpioError> {
-        let active_level = match self.active_level {
-            Some(active_level) => active_level,
-            None => return Err(general::GpioError::InvalidFlag),
+        if self.constraints.denies_digital(&self.flags) {
+            return Err(general::GpioError::InvalidFlag);
+        }
+
+        let forced = self
+            .constraints
+            .active_level
+            .map(general::ActiveLevel::from);
+        let active_level = match (self.active_level, forced) {
+            (Some(active_level), Some(forced)) if active_level != forced => {
+                return Err(general::GpioError::InvalidFlag);
+            }
+            (Some(active_level), _) | (None, Some(active_level)) => active_level,
+            (None, None) => return Err(general::GpioError::InvalidFlag),
         };
 
         match self.pin_mode {
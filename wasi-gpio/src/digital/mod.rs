@@ -11,6 +11,8 @@ pub struct DigitalConfigBuilder {
     pin_mode: general::PinMode,
     active_level: Option<general::ActiveLevel>,
     pull_resistor: Option<general::PullResistor>,
+    flags: Vec<digital::DigitalFlag>,
+    constraints: policies::PinConstraints,
 }
 
 impl<'a, T: WasiGpioView> digital::Host for GpioImpl<'a, T> {}
@@ -24,23 +26,52 @@ pub struct DigitalInPin {
 fn get_pin(
     ctx: &crate::ctx::WasiGpioCtx,
     label: &str,
+    flags: &[digital::DigitalFlag],
 ) -> Result<rppal::gpio::Pin, general::GpioError> {
-    let plabel = ctx
+    let pin = ctx
         .policies
-        .get_plabel(label)
+        .get_pin(label)
         .ok_or_else(|| general::GpioError::Other("Pin not found in policy".to_string()))?;
 
-    let pin_num = plabel
-        .strip_prefix("GPIO")
-        .and_then(|s| s.parse::<u8>().ok())
-        .ok_or_else(|| general::GpioError::Other("Invalid physical label format".to_string()))?;
+    let pull = flags.iter().any(|flag| {
+        *flag == digital::DigitalFlag::PULL_UP || *flag == digital::DigitalFlag::PULL_DOWN
+    });
+
+    if pull && !pin.pull {
+        return Err(general::GpioError::InvalidFlag);
+    }
 
     rppal::gpio::Gpio::new()
         .map_err(|e| general::GpioError::Other(e.to_string()))?
-        .get(pin_num)
+        .get(pin.bcm)
         .map_err(|e| general::GpioError::Other(e.to_string()))
 }
 
+impl<T: WasiGpioView> GpioImpl<'_, T> {
+    /// Returns the level an in-out pin is driven to when it becomes an output
+    ///
+    /// That is the initial state of its constraints, which has to pass the interlock, or else the off level of its interlock
+    fn output_start_level(
+        &mut self,
+        config: &digital::DigitalConfig,
+    ) -> Result<Option<rppal::gpio::Level>, general::GpioError> {
+        let initial_state = self
+            .ctx()
+            .policies
+            .get_constraints(&config.label)
+            .and_then(|constraints| constraints.initial_state);
+
+        match initial_state {
+            Some(state) => {
+                let level = implementations::level_for(config.active_level, state.into());
+                self.interlock(&config.label, level)?;
+                Ok(Some(level))
+            }
+            None => Ok(self.interlock_off_level(&config.label)),
+        }
+    }
+}
+
 impl<'a, T: WasiGpioView> digital::HostDigitalInPin for GpioImpl<'a, T> {
     fn get(
         &mut self,
@@ -65,10 +96,17 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInPin for GpioImpl<'a, T> {
         )
         .map_err(|_| general::GpioError::InvalidFlag)?;
 
-        let pin = get_pin(self.ctx(), &pin_label)?;
+        let pin = get_pin(self.ctx(), &pin_label, &flags)?;
+
+        let constraints = self
+            .ctx()
+            .policies
+            .get_constraints(&pin_label)
+            .unwrap_or_default();
 
         let config = DigitalConfigBuilder::new(pin_label, general::PinMode::In)
             .add_flags(flags)
+            .constrain(constraints)
             .build()
             .map_err(|_| general::GpioError::InvalidFlag)?;
 
@@ -121,7 +159,7 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInPin for GpioImpl<'a, T> {
         &mut self,
         self_: Resource<DigitalInPin>,
         state: digital::PinState,
-    ) -> Result<Resource<poll::Pollable>, general::GpioError> {
+    ) -> Result<Resource<poll::PollableResource>, general::GpioError> {
         let pin = self
             .table()
             .get(&self_)
@@ -143,22 +181,21 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInPin for GpioImpl<'a, T> {
         // not referencing the table inside `self`.
         let trigger = self.ctx().watcher.watch_event(&pin, watch_type);
 
-        self.table()
-            .push(poll::Pollable::new(trigger))
+        self.push_pollable(poll::Pollable::new(trigger))
             .map_err(|err| general::GpioError::Other(err.to_string()))
     }
 
     fn watch_active(
         &mut self,
         self_: Resource<DigitalInPin>,
-    ) -> Result<Resource<poll::Pollable>, general::GpioError> {
+    ) -> Result<Resource<poll::PollableResource>, general::GpioError> {
         self.watch_state(self_, digital::PinState::Active)
     }
 
     fn watch_inactive(
         &mut self,
         self_: Resource<DigitalInPin>,
-    ) -> Result<Resource<poll::Pollable>, general::GpioError> {
+    ) -> Result<Resource<poll::PollableResource>, general::GpioError> {
         self.watch_state(self_, digital::PinState::Inactive)
     }
 
@@ -166,7 +203,7 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInPin for GpioImpl<'a, T> {
     fn watch_falling_edge(
         &mut self,
         self_: Resource<DigitalInPin>,
-    ) -> Result<Resource<poll::Pollable>, general::GpioError> {
+    ) -> Result<Resource<poll::PollableResource>, general::GpioError> {
         let pin = self
             .table()
             .get(&self_)
@@ -180,8 +217,7 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInPin for GpioImpl<'a, T> {
 
         let trigger = self.ctx().watcher.watch_event(&pin, watch_event);
 
-        self.table()
-            .push(poll::Pollable::new(trigger))
+        self.push_pollable(poll::Pollable::new(trigger))
             .map_err(|err| general::GpioError::Other(err.to_string()))
     }
 
@@ -189,7 +225,7 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInPin for GpioImpl<'a, T> {
     fn watch_rising_edge(
         &mut self,
         self_: Resource<DigitalInPin>,
-    ) -> Result<Resource<poll::Pollable>, general::GpioError> {
+    ) -> Result<Resource<poll::PollableResource>, general::GpioError> {
         let pin = self
             .table()
             .get(&self_)
@@ -203,8 +239,7 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInPin for GpioImpl<'a, T> {
 
         let trigger = self.ctx().watcher.watch_event(&pin, watch_event);
 
-        self.table()
-            .push(poll::Pollable::new(trigger))
+        self.push_pollable(poll::Pollable::new(trigger))
             .map_err(|err| general::GpioError::Other(err.to_string()))
     }
 
@@ -215,7 +250,7 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInPin for GpioImpl<'a, T> {
 }
 
 pub struct DigitalOutPin {
-    pub pin: rppal::gpio::OutputPin,
+    pub pin: util::Shared<rppal::gpio::OutputPin>,
     pub config: digital::DigitalConfig,
 }
 
@@ -233,6 +268,11 @@ impl<'a, T: WasiGpioView> digital::HostDigitalOutPin for GpioImpl<'a, T> {
             return Err(general::GpioError::PinModeNotAllowed);
         }
 
+        // A digital output can stay active for any time, which a duty cycle limit does not allow
+        if self.ctx().policies.is_duty_limited(&pin_label) {
+            return Err(general::GpioError::PinModeNotAllowed);
+        }
+
         implementations::check_invalid_flags(
             &flags,
             vec![
@@ -243,26 +283,56 @@ impl<'a, T: WasiGpioView> digital::HostDigitalOutPin for GpioImpl<'a, T> {
         )
         .map_err(|_| general::GpioError::InvalidFlag)?;
 
-        let mut pin_state = None;
-        for flag in flags.iter() {
-            if *flag == digital::DigitalFlag::ACTIVE {
-                pin_state = Some(digital::PinState::Active);
-            } else if *flag == digital::DigitalFlag::INACTIVE {
-                // Preserving logic from original file: Inactive flag mapped to Active state intent for initialization?
-                // Alternatively, this might be a bug in the original source, but keeping it consistent.
-                pin_state = Some(digital::PinState::Active);
+        let mut pin_state = implementations::initial_state(&flags);
+
+        let pin = get_pin(self.ctx(), &pin_label, &flags)?;
+
+        let constraints = self
+            .ctx()
+            .policies
+            .get_constraints(&pin_label)
+            .unwrap_or_default();
+
+        // The required initial state wins, asking for the other one is an error
+        if let Some(required) = constraints.initial_state {
+            let conflicting = match required {
+                policies::InitialState::Active => digital::DigitalFlag::INACTIVE,
+                policies::InitialState::Inactive => digital::DigitalFlag::ACTIVE,
+            };
+
+            if flags.contains(&conflicting) {
+                return Err(general::GpioError::InvalidFlag);
             }
-        }
 
-        let pin = get_pin(self.ctx(), &pin_label)?;
+            pin_state = Some(required.into());
+        }
 
         let config = DigitalConfigBuilder::new(pin_label, digital::PinMode::Out)
             .add_flags(flags)
+            .constrain(constraints)
             .build()
             .map_err(|_| general::GpioError::InvalidFlag)?;
 
+        // Interlocked pins start switched off unless the component asks for a state, which has to pass the interlock
+        if let Some(off_level) = self.interlock_off_level(&config.label) {
+            let state =
+                pin_state.unwrap_or(implementations::state_for(config.active_level, off_level));
+
+            self.interlock(
+                &config.label,
+                implementations::level_for(config.active_level, state),
+            )?;
+            pin_state = Some(state);
+        }
+
+        let mut pin = DigitalOutPin::new(pin, config, pin_state);
+
+        if self.keeps_state(&pin.config.label) {
+            pin.keep_state();
+        }
+
         self.table()
-            .push(DigitalOutPin::new(pin, config, pin_state))
+            .push(pin)
             .map_err(|err| general::GpioError::Other(err.to_string()))
     }
 
@@ -290,7 +360,29 @@ impl<'a, T: WasiGpioView> digital::HostDigitalOutPin for GpioImpl<'a, T> {
         self_: Resource<DigitalOutPin>,
         state: digital::PinState,
     ) -> Result<(), general::GpioError> {
-        self.table().get_mut(&self_).unwrap().write(state);
+        let pin = self
+            .table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?;
+        let label = pin.config.label.clone();
+        let level = pin.get_level(state);
+
+        let changes = pin.changes(state);
+
+        if changes {
+            self.limit_rate(&label)?;
+        }
+
+        self.interlock(&label, level)?;
+
+        self.table()
+            .get_mut(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .write(state);
+
+        if changes {
+            self.record_change(&label);
+        }
         Ok(())
     }
 
@@ -303,8 +395,11 @@ impl<'a, T: WasiGpioView> digital::HostDigitalOutPin for GpioImpl<'a, T> {
     }
 
     fn drop(&mut self, rep: Resource<DigitalOutPin>) -> wasmtime::Result<()> {
-        self.table().delete(rep).expect("failed to delete resource");
-        Ok(())
+        let pin = self.table().delete(rep).expect("failed to delete resource");
+        let label = pin.config.label.clone();
+        std::mem::drop(pin);
+        self.release_interlock(&label);
+        self.apply_safe_state(&label)
     }
 }
 
@@ -338,10 +433,33 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInOutPin for GpioImpl<'a, T> {
         self_: Resource<DigitalInOutPin>,
         state: digital::PinState,
     ) -> Result<(), general::GpioError> {
+        let pin = self
+            .table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?;
+        let label = pin.config.label.clone();
+        let level = pin.get_level(state);
+        let is_output = pin.is_output();
+
+        let changes = pin.changes(state);
+
+        if changes {
+            self.limit_rate(&label)?;
+        }
+
+        // An input only drives the level once it becomes an output, which starts interlocked pins switched off
+        if is_output {
+            self.interlock(&label, level)?;
+        }
+
         self.table()
             .get_mut(&self_)
             .map_err(|_| general::GpioError::ResourceInvalidated)?
             .write(state);
+
+        if changes {
+            self.record_change(&label);
+        }
         Ok(())
     }
 
@@ -380,8 +498,11 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInOutPin for GpioImpl<'a, T> {
     }
 
     fn drop(&mut self, rep: Resource<DigitalInOutPin>) -> wasmtime::Result<()> {
-        self.table().delete(rep).expect("failed to delete resource");
-        Ok(())
+        let pin = self.table().delete(rep).expect("failed to delete resource");
+        let label = pin.config.label.clone();
+        std::mem::drop(pin);
+        self.release_interlock(&label);
+        self.apply_safe_state(&label)
     }
 
     fn get(
@@ -397,6 +518,11 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInOutPin for GpioImpl<'a, T> {
             return Err(general::GpioError::PinModeNotAllowed);
         }
 
+        // A digital output can stay active for any time, which a duty cycle limit does not allow
+        if self.ctx().policies.is_duty_limited(&pin_label) {
+            return Err(general::GpioError::PinModeNotAllowed);
+        }
+
         implementations::check_invalid_flags(
             &flags,
             vec![
@@ -408,7 +534,7 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInOutPin for GpioImpl<'a, T> {
         )
         .map_err(|_| general::GpioError::InvalidFlag)?;
 
-        let pin = get_pin(self.ctx(), &pin_label)?;
+        let pin = get_pin(self.ctx(), &pin_label, &flags)?;
 
         let mut pin_mode = None;
 
@@ -431,13 +557,31 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInOutPin for GpioImpl<'a, T> {
             None => return Err(general::GpioError::InvalidFlag),
         };
 
+        let constraints = self
+            .ctx()
+            .policies
+            .get_constraints(&pin_label)
+            .unwrap_or_default();
+
         let config = DigitalConfigBuilder::new(pin_label, pin_mode)
             .add_flags(flags)
+            .constrain(constraints)
             .build()
             .map_err(|_| general::GpioError::InvalidFlag)?;
 
+        let start_level = match pin_mode {
+            digital::PinMode::Out => self.output_start_level(&config)?,
+            digital::PinMode::In => None,
+        };
+
+        let mut pin = DigitalInOutPin::new(pin, config, pin_mode, start_level);
+
+        if self.keeps_state(&pin.config.label) {
+            pin.keep_state();
+        }
+
         self.table()
-            .push(DigitalInOutPin::new(pin, config, pin_mode))
+            .push(pin)
             .map_err(|err| general::GpioError::Other(err.to_string()))
     }
 
@@ -446,11 +590,30 @@ impl<'a, T: WasiGpioView> digital::HostDigitalInOutPin for GpioImpl<'a, T> {
         self_: Resource<DigitalInOutPin>,
         pin_mode: general::PinMode,
     ) -> Result<(), general::GpioError> {
-        Ok(self
+        let pin = self
             .table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?;
+        let becomes_output = pin_mode == general::PinMode::Out && !pin.is_output();
+        let config = pin.config.clone();
+        let label = config.label.clone();
+
+        let start_level = if becomes_output {
+            self.output_start_level(&config)?
+        } else {
+            None
+        };
+
+        self.table()
             .get_mut(&self_)
             .map_err(|_| general::GpioError::ResourceInvalidated)?
-            .set_pin_mode(pin_mode))
+            .set_pin_mode(pin_mode, start_level);
+
+        if pin_mode == general::PinMode::In {
+            self.release_interlock(&label);
+        }
+
+        Ok(())
     }
 }
 
@@ -0,0 +1,120 @@
+use crate::ctx::WasiGpioView;
+use crate::digital::DigitalInPin;
+use crate::impls::GpioImpl;
+use crate::util::Shared;
+use crate::wasi::gpio::{encoder, general};
+use crate::{poll, watch_event};
+use wasmtime::component::Resource;
+
+pub struct QuadratureEncoder {
+    pub quadrature: Shared<watch_event::Quadrature>,
+}
+
+impl QuadratureEncoder {
+    pub fn new(quadrature: Shared<watch_event::Quadrature>) -> Self {
+        Self { quadrature }
+    }
+}
+
+impl<'a, T: WasiGpioView> encoder::Host for GpioImpl<'a, T> {}
+
+impl<'a, T: WasiGpioView> GpioImpl<'a, T> {
+    fn get_quadrature(
+        &mut self,
+        self_: &Resource<QuadratureEncoder>,
+    ) -> Result<Shared<watch_event::Quadrature>, general::GpioError> {
+        Ok(self
+            .table()
+            .get(self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .quadrature
+            .clone())
+    }
+}
+
+impl<'a, T: WasiGpioView> encoder::HostQuadratureEncoder for GpioImpl<'a, T> {
+    fn get(
+        &mut self,
+        pin_a: Resource<DigitalInPin>,
+        pin_b: Resource<DigitalInPin>,
+        transitions_per_step: u32,
+    ) -> Result<Resource<QuadratureEncoder>, general::GpioError> {
+        let pin_a = self
+            .table()
+            .get(&pin_a)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .clone();
+
+        let pin_b = self
+            .table()
+            .get(&pin_b)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .clone();
+
+        // Two labels of one physical pin would never see a quadrature signal
+        if self
+            .ctx()
+            .policies
+            .is_same_pin(&pin_a.config.label, &pin_b.config.label)
+        {
+            return Err(general::GpioError::AlreadyInUse);
+        }
+
+        let quadrature = self
+            .ctx()
+            .watcher
+            .watch_quadrature(&pin_a, &pin_b, transitions_per_step);
+
+        self.table()
+            .push(QuadratureEncoder::new(quadrature))
+            .map_err(|err| general::GpioError::Other(err.to_string()))
+    }
+
+    fn position(&mut self, self_: Resource<QuadratureEncoder>) -> Result<i64, general::GpioError> {
+        Ok(self.get_quadrature(&self_)?.lock().unwrap().position())
+    }
+
+    fn set_position(
+        &mut self,
+        self_: Resource<QuadratureEncoder>,
+        position: i64,
+    ) -> Result<(), general::GpioError> {
+        self.get_quadrature(&self_)?
+            .lock()
+            .unwrap()
+            .set_position(position);
+        Ok(())
+    }
+
+    fn direction(
+        &mut self,
+        self_: Resource<QuadratureEncoder>,
+    ) -> Result<Option<encoder::Direction>, general::GpioError> {
+        let direction = self.get_quadrature(&self_)?.lock().unwrap().direction();
+
+        Ok(direction.map(|direction| match direction {
+            1 => encoder::Direction::Clockwise,
+            _ => encoder::Direction::CounterClockwise,
+        }))
+    }
+
+    fn velocity(&mut self, self_: Resource<QuadratureEncoder>) -> Result<f32, general::GpioError> {
+        Ok(self.get_quadrature(&self_)?.lock().unwrap().velocity())
+    }
+
+    fn watch_change(
+        &mut self,
+        self_: Resource<QuadratureEncoder>,
+    ) -> Result<Resource<poll::PollableResource>, general::GpioError> {
+        let trigger = self.get_quadrature(&self_)?.lock().unwrap().watch_change();
+
+        self.push_pollable(poll::Pollable::new(trigger))
+            .map_err(|err| general::GpioError::Other(err.to_string()))
+    }
+
+    fn drop(&mut self, rep: Resource<QuadratureEncoder>) -> wasmtime::Result<()> {
+        let encoder = self.table().delete(rep).expect("failed to delete resource");
+        encoder.quadrature.lock().unwrap().stop();
+        Ok(())
+    }
+}
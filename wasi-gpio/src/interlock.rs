@@ -0,0 +1,278 @@
+use crate::ctx::WasiGpioView;
+use crate::delay;
+use crate::impls::GpioImpl;
+use crate::policies::Policies;
+use crate::wasi::gpio::general;
+use rppal::gpio::Level;
+use std::time::{Duration, Instant};
+
+struct InterlockGroup {
+    /// (BCM number, virtual label) of the pins in the group
+    pins: Vec<(u8, String)>,
+    on_level: Level,
+    dead_time: Duration,
+    /// Pin that is switched on, at most one at a time
+    on: Option<u8>,
+    /// When the last pin that was on switched off
+    released: Option<Instant>,
+}
+
+impl InterlockGroup {
+    fn label(&self, bcm: u8) -> &str {
+        self.pins
+            .iter()
+            .find(|(pin, _)| *pin == bcm)
+            .map_or("", |(_, label)| label)
+    }
+
+    fn contains(&self, bcm: u8) -> bool {
+        self.pins.iter().any(|(pin, _)| *pin == bcm)
+    }
+}
+
+/// State of the interlocks of the policy, shared by every resource of the component
+#[derive(Default)]
+pub struct Interlocks {
+    groups: Vec<InterlockGroup>,
+}
+
+impl Interlocks {
+    pub fn new(policies: &Policies) -> Self {
+        let groups = policies
+            .wasi
+            .interlock
+            .iter()
+            .map(|interlock| InterlockGroup {
+                pins: interlock
+                    .pins
+                    .iter()
+                    .filter_map(|vlabel| {
+                        let pin = policies.get_pin(vlabel.get_ref())?;
+                        Some((pin.bcm, vlabel.get_ref().clone()))
+                    })
+                    .collect(),
+                on_level: interlock.on_level.into(),
+                dead_time: Duration::from_micros(interlock.dead_time_us),
+                on: None,
+                released: None,
+            })
+            .collect();
+
+        Self { groups }
+    }
+
+    /// Returns the level that switches `bcm` off, none when the pin is not interlocked
+    pub fn off_level(&self, bcm: u8) -> Option<Level> {
+        self.groups
+            .iter()
+            .find(|group| group.contains(bcm))
+            .map(|group| !group.on_level)
+    }
+
+    /// Claims the interlocks of `bcm` before it gets driven to `level`, waits out the dead time when needed
+    pub fn switch(&mut self, bcm: u8, level: Level) -> Result<(), general::GpioError> {
+        let mut groups: Vec<_> = self
+            .groups
+            .iter_mut()
+            .filter(|group| group.contains(bcm))
+            .collect();
+
+        // Every group is checked before any changes so a refused switch leaves them as they are
+        for group in groups.iter() {
+            if level != group.on_level {
+                continue;
+            }
+
+            if let Some(on) = group.on
+                && on != bcm
+            {
+                return Err(general::GpioError::Other(format!(
+                    "`{}` is interlocked with `{}`, which is on",
+                    group.label(bcm),
+                    group.label(on)
+                )));
+            }
+        }
+
+        let deadline = groups
+            .iter()
+            .filter(|group| level == group.on_level && group.on.is_none())
+            .filter_map(|group| group.released.map(|released| released + group.dead_time))
+            .max();
+
+        if let Some(deadline) = deadline {
+            delay::wait_until(deadline, delay::DEFAULT_SPIN_THRESHOLD);
+        }
+
+        for group in groups.iter_mut() {
+            if level == group.on_level {
+                group.on = Some(bcm);
+            } else if group.on == Some(bcm) {
+                group.on = None;
+                group.released = Some(Instant::now());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Releases the interlocks of `bcm` once it stops driving its output
+    pub fn release(&mut self, bcm: u8) {
+        for group in self.groups.iter_mut() {
+            if group.on == Some(bcm) {
+                group.on = None;
+                group.released = Some(Instant::now());
+            }
+        }
+    }
+}
+
+impl<'a, T: WasiGpioView> GpioImpl<'a, T> {
+    /// Applies the interlocks of the policy before the output of `vlabel` gets driven to `level`
+    pub(crate) fn interlock(
+        &mut self,
+        vlabel: &str,
+        level: Level,
+    ) -> Result<(), general::GpioError> {
+        let ctx = self.ctx();
+
+        match ctx.policies.get_pin(vlabel) {
+            Some(pin) => ctx.interlocks.switch(pin.bcm, level),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the level that switches `vlabel` off, none when the pin is not interlocked
+    pub(crate) fn interlock_off_level(&mut self, vlabel: &str) -> Option<Level> {
+        let ctx = self.ctx();
+        ctx.interlocks.off_level(ctx.policies.get_pin(vlabel)?.bcm)
+    }
+
+    pub(crate) fn release_interlock(&mut self, vlabel: &str) {
+        let ctx = self.ctx();
+
+        if let Some(pin) = ctx.policies.get_pin(vlabel) {
+            ctx.interlocks.release(pin.bcm);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BRIDGE_HIGH: u8 = 17;
+    const BRIDGE_LOW: u8 = 27;
+
+    fn interlocks(on_level: &str, dead_time_us: u64) -> Interlocks {
+        let policies: Policies = toml::from_str(&format!(
+            r#"
+            [[wasi.gpio]]
+            vlabel = "BRIDGE_HIGH"
+            modes = ["digital-output"]
+            plabel = "GPIO17"
+
+            [[wasi.gpio]]
+            vlabel = "BRIDGE_LOW"
+            modes = ["digital-output"]
+            plabel = "GPIO27"
+
+            [[wasi.interlock]]
+            pins = ["BRIDGE_HIGH", "BRIDGE_LOW"]
+            on-level = "{on_level}"
+            dead-time-us = {dead_time_us}
+            "#
+        ))
+        .unwrap();
+
+        Interlocks::new(&policies)
+    }
+
+    #[test]
+    fn inactive_flag_leaves_an_interlocked_pin_off() {
+        use crate::digital::implementations::{initial_state, level_for};
+        use crate::wasi::gpio::digital::DigitalFlag;
+
+        // The on level of the interlock matches the active level of the pins
+        for (on_level, active_level) in [
+            ("high", general::ActiveLevel::ActiveHigh),
+            ("low", general::ActiveLevel::ActiveLow),
+        ] {
+            let mut interlocks = interlocks(on_level, 0);
+            let state = initial_state(&[DigitalFlag::INACTIVE]).unwrap();
+            let level = level_for(active_level, state);
+
+            assert_eq!(interlocks.off_level(BRIDGE_HIGH), Some(level), "{on_level}");
+            assert!(interlocks.switch(BRIDGE_HIGH, level).is_ok());
+
+            // Nothing was claimed, so the other pin can switch on
+            assert!(interlocks.switch(BRIDGE_LOW, !level).is_ok(), "{on_level}");
+        }
+    }
+
+    #[test]
+    fn refuses_a_second_pin_while_one_is_on() {
+        let mut interlocks = interlocks("high", 0);
+
+        assert!(interlocks.switch(BRIDGE_HIGH, Level::High).is_ok());
+        assert!(interlocks.switch(BRIDGE_LOW, Level::High).is_err());
+
+        // Switching the pin that is on again, or switching the other one off, needs no claim
+        assert!(interlocks.switch(BRIDGE_HIGH, Level::High).is_ok());
+        assert!(interlocks.switch(BRIDGE_LOW, Level::Low).is_ok());
+    }
+
+    #[test]
+    fn switching_off_frees_the_claim() {
+        let mut interlocks = interlocks("high", 0);
+
+        interlocks.switch(BRIDGE_HIGH, Level::High).unwrap();
+        interlocks.switch(BRIDGE_HIGH, Level::Low).unwrap();
+
+        assert!(interlocks.switch(BRIDGE_LOW, Level::High).is_ok());
+    }
+
+    #[test]
+    fn release_frees_the_claim() {
+        let mut interlocks = interlocks("high", 0);
+
+        interlocks.switch(BRIDGE_HIGH, Level::High).unwrap();
+        interlocks.release(BRIDGE_HIGH);
+
+        assert!(interlocks.switch(BRIDGE_LOW, Level::High).is_ok());
+    }
+
+    #[test]
+    fn on_level_low_inverts_the_levels() {
+        let mut interlocks = interlocks("low", 0);
+
+        assert_eq!(interlocks.off_level(BRIDGE_HIGH), Some(Level::High));
+        assert_eq!(interlocks.off_level(22), None);
+
+        interlocks.switch(BRIDGE_HIGH, Level::Low).unwrap();
+        assert!(interlocks.switch(BRIDGE_LOW, Level::Low).is_err());
+        assert!(interlocks.switch(BRIDGE_LOW, Level::High).is_ok());
+    }
+
+    #[test]
+    fn waits_out_the_dead_time() {
+        let dead_time = Duration::from_millis(20);
+        let mut interlocks = interlocks("high", dead_time.as_micros() as u64);
+
+        interlocks.switch(BRIDGE_HIGH, Level::High).unwrap();
+        interlocks.switch(BRIDGE_HIGH, Level::Low).unwrap();
+
+        let start = Instant::now();
+        interlocks.switch(BRIDGE_LOW, Level::High).unwrap();
+        assert!(start.elapsed() >= dead_time);
+    }
+
+    #[test]
+    fn dead_time_is_not_waited_without_a_release() {
+        let mut interlocks = interlocks("high", 1_000_000);
+
+        let start = Instant::now();
+        interlocks.switch(BRIDGE_HIGH, Level::High).unwrap();
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+}
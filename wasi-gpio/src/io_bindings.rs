@@ -0,0 +1,185 @@
+//! Bindings for `wasi:io/poll` that replace the ones of wasmtime-wasi, so waiting on any pollable stops once the host cancels the component.
+//! The pollables are the same `DynPollable` resources as those of wasmtime-wasi and `wasi:gpio/poll`.
+
+use crate::ctx::WasiGpioView;
+use crate::impls::GpioImpl;
+use crate::wasi::gpio::poll as gpio;
+use wasmtime::component::{Linker, Resource};
+use wasmtime_wasi::p2::DynPollable;
+
+wasmtime::component::bindgen!({
+    path: "../wit-io/deps/io",
+    world: "wasi:io/imports",
+    imports: {
+        // Blocking calls trap when the host cancels them
+        "wasi:io/poll.poll": trappable,
+        "wasi:io/poll.[method]pollable.block": trappable,
+    },
+    with: {
+        "wasi:io/error": wasmtime_wasi::p2::bindings::io::error,
+        "wasi:io/streams": wasmtime_wasi::p2::bindings::sync::io::streams,
+        "wasi:io/poll.pollable": wasmtime_wasi::p2::DynPollable,
+    }
+});
+
+use self::wasi::io::poll;
+
+impl<T: WasiGpioView> poll::Host for GpioImpl<'_, T> {
+    fn poll(&mut self, in_: Vec<Resource<DynPollable>>) -> wasmtime::Result<Vec<u32>> {
+        // wasi:io traps on an empty list, unlike the legacy interface
+        if in_.is_empty() {
+            anyhow::bail!("empty poll list");
+        }
+
+        gpio::Host::poll(self, in_)
+    }
+}
+
+impl<T: WasiGpioView> poll::HostPollable for GpioImpl<'_, T> {
+    fn ready(&mut self, self_: Resource<DynPollable>) -> bool {
+        gpio::HostPollable::ready(self, self_)
+    }
+
+    fn block(&mut self, self_: Resource<DynPollable>) -> wasmtime::Result<()> {
+        gpio::HostPollable::block(self, self_)
+    }
+
+    fn drop(&mut self, rep: Resource<DynPollable>) -> wasmtime::Result<()> {
+        gpio::HostPollable::drop(self, rep)
+    }
+}
+
+/// Defines `wasi:io/poll` in `linker`, replacing the definition of wasmtime-wasi when there is one
+///
+/// The linker has no getter for its shadowing setting, so it is set back to the default of wasmtime, which is off.
+pub(crate) fn shadow_io_poll<T, F>(linker: &mut Linker<T>, add: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&mut Linker<T>) -> anyhow::Result<()>,
+{
+    linker.allow_shadowing(true);
+    let result = add(linker);
+    linker.allow_shadowing(false);
+
+    result
+}
+
+pub(crate) fn add_to_linker<T>(linker: &mut Linker<T>) -> anyhow::Result<()>
+where
+    T: WasiGpioView + 'static,
+{
+    shadow_io_poll(linker, |linker| {
+        poll::add_to_linker::<T, crate::GpioBindingMarker<T>>(linker, |host| GpioImpl { host })
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::ctx::{WasiGpioCtx, WasiGpioView};
+    use wasmtime::component::{Component, Linker, ResourceTable};
+    use wasmtime::{Engine, Store};
+    use wasmtime_wasi::{WasiCtx, WasiCtxView, WasiView};
+
+    /// Component built against `wit-io` that blocks on a `wasi:io/poll` pollable from `wasi:gpio/delay.subscribe-ms`
+    ///
+    /// Its `run` export takes the milliseconds to wait
+    pub(crate) const WIT_IO_COMPONENT: &str = r#"
+        (component $c
+            (import "wasi:io/poll@0.2.6" (instance $io-poll
+                (export "pollable" (type $pollable (sub resource)))
+                (export "[method]pollable.block" (func (param "self" (borrow $pollable))))
+            ))
+            (alias export $io-poll "pollable" (type $pollable))
+            (import "wasi:gpio/poll@0.2.1" (instance
+                (alias outer $c $pollable (type $p))
+                (export "pollable" (type (eq $p)))
+            ))
+            (import "wasi:gpio/delay@0.2.1" (instance $delay
+                (alias outer $c $pollable (type $p))
+                (export "pollable" (type $pollable (eq $p)))
+                (export "subscribe-ms" (func (param "ms" u64) (result (own $pollable))))
+            ))
+
+            (core func $subscribe-ms (canon lower (func $delay "subscribe-ms")))
+            (core func $block (canon lower (func $io-poll "[method]pollable.block")))
+
+            (core module $m
+                (import "delay" "subscribe-ms" (func $subscribe-ms (param i64) (result i32)))
+                (import "poll" "block" (func $block (param i32)))
+                (func (export "run") (param i64)
+                    (call $block (call $subscribe-ms (local.get 0))))
+            )
+            (core instance $i (instantiate $m
+                (with "delay" (instance (export "subscribe-ms" (func $subscribe-ms))))
+                (with "poll" (instance (export "block" (func $block))))
+            ))
+
+            (func (export "run") (param "ms" u64) (canon lift (core func $i "run")))
+        )
+    "#;
+
+    pub(crate) struct TestHost {
+        ctx: WasiCtx,
+        table: ResourceTable,
+        pub(crate) gpio_ctx: WasiGpioCtx,
+    }
+
+    impl WasiView for TestHost {
+        fn ctx(&mut self) -> WasiCtxView<'_> {
+            WasiCtxView {
+                ctx: &mut self.ctx,
+                table: &mut self.table,
+            }
+        }
+    }
+
+    impl WasiGpioView for TestHost {
+        fn gpio_ctx(&mut self) -> &mut WasiGpioCtx {
+            &mut self.gpio_ctx
+        }
+
+        fn table(&mut self) -> &mut ResourceTable {
+            &mut self.table
+        }
+    }
+
+    impl TestHost {
+        pub(crate) fn new() -> Self {
+            Self {
+                ctx: WasiCtx::builder().build(),
+                table: ResourceTable::new(),
+                gpio_ctx: WasiGpioCtx::new(toml::from_str("wasi.gpio = []").unwrap()),
+            }
+        }
+    }
+
+    /// Runs the `run` export of `WIT_IO_COMPONENT`, after cancelling the store when `cancel` is set
+    fn run(ms: u64, cancel: bool) -> wasmtime::Result<()> {
+        let engine = Engine::default();
+        let mut linker = Linker::new(&engine);
+        wasmtime_wasi::p2::add_to_linker_sync(&mut linker)?;
+        crate::add_to_linker(&mut linker)?;
+
+        let component = Component::new(&engine, WIT_IO_COMPONENT)?;
+        let mut store = Store::new(&engine, TestHost::new());
+        if cancel {
+            store.data().gpio_ctx.cancel.cancel();
+        }
+
+        let instance = linker.instantiate(&mut store, &component)?;
+        let run = instance.get_typed_func::<(u64,), ()>(&mut store, "run")?;
+        run.call(&mut store, (ms,))
+    }
+
+    #[test]
+    fn wit_io_components_block_on_wasi_io_pollables() {
+        run(1, false).unwrap();
+    }
+
+    #[test]
+    fn cancelling_stops_a_wasi_io_block() {
+        let start = std::time::Instant::now();
+
+        assert!(run(60_000, true).is_err());
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+}
@@ -1,15 +1,29 @@
 use wasmtime::component::Linker;
 
 pub mod analog;
+pub mod async_bindings;
+pub mod board;
+pub mod counter;
 pub mod ctx;
 pub mod delay;
 pub mod digital;
+pub mod encoder;
 pub mod general;
 pub mod impls;
+pub mod interlock;
+mod io_bindings;
 pub mod policies;
 pub mod poll;
+pub mod port;
+pub mod rate_limit;
+pub mod safe_state;
+pub mod stepper;
+pub mod tone;
 pub mod util;
+mod v0_2_0;
 pub mod watch_event;
+pub mod watchdog;
+pub mod waveform;
 
 pub use ctx::{GpioBindingMarker, WasiGpioCtx, WasiGpioView};
 use impls::GpioImpl;
@@ -17,6 +31,19 @@ use impls::GpioImpl;
 wasmtime::component::bindgen!({
     path: "../wit",
     world: "rpi",
+    imports: {
+        // Blocking calls trap when the host cancels them
+        "wasi:gpio/delay.delay-ns": trappable,
+        "wasi:gpio/delay.delay-us": trappable,
+        "wasi:gpio/delay.delay-ms": trappable,
+        // Subscribing traps when the resource table is full
+        "wasi:gpio/delay.subscribe-ns": trappable,
+        "wasi:gpio/delay.subscribe-us": trappable,
+        "wasi:gpio/delay.subscribe-ms": trappable,
+        "wasi:gpio/delay.subscribe-deadline-ns": trappable,
+        "wasi:gpio/poll.poll": trappable,
+        "wasi:gpio/poll.[method]pollable.block": trappable,
+    },
     with: {
         // Digital module resources
         "wasi:gpio/digital.digital-out-pin": crate::digital::DigitalOutPin,
@@ -29,11 +56,33 @@ wasmtime::component::bindgen!({
         "wasi:gpio/analog.analog-out-pin": crate::analog::AnalogOutPin,
         "wasi:gpio/analog.analog-in-out-pin": crate::analog::AnalogInOutPin,
 
+        // Counter module resources
+        "wasi:gpio/counter.edge-counter": crate::counter::EdgeCounter,
+
+        // Encoder module resources
+        "wasi:gpio/encoder.quadrature-encoder": crate::encoder::QuadratureEncoder,
+
+        // Port module resources
+        "wasi:gpio/port.digital-port": crate::port::DigitalPort,
+
+        // Waveform module resources
+        "wasi:gpio/waveform.waveform": crate::waveform::Waveform,
+
+        // Stepper module resources
+        "wasi:gpio/stepper.stepper": crate::stepper::Stepper,
+
+        // Tone module resources
+        "wasi:gpio/tone.tone": crate::tone::Tone,
+
         // Poll and Delay resources
-        "wasi:gpio/poll.pollable": crate::poll::Pollable,
+        "wasi:gpio/poll.pollable": wasmtime_wasi::p2::DynPollable,
     }
 });
 
+/// Adds the `wasi:gpio` interfaces to `linker`, those of 0.2.1 and the ones of 0.2.0 whose types differ
+///
+/// This also replaces `wasi:io/poll`, so waiting on a pollable stops when the host cancels the component.
+/// Call it after adding wasmtime-wasi, and note that it leaves shadowing disabled on `linker`.
 pub fn add_to_linker<T>(linker: &mut Linker<T>) -> anyhow::Result<()>
 where
     T: WasiGpioView + 'static,
@@ -49,5 +98,73 @@ where
         host,
     })?;
     wasi::gpio::analog::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl { host })?;
+    wasi::gpio::counter::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl {
+        host,
+    })?;
+    wasi::gpio::encoder::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl {
+        host,
+    })?;
+    wasi::gpio::port::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl { host })?;
+    wasi::gpio::waveform::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl {
+        host,
+    })?;
+    wasi::gpio::stepper::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl {
+        host,
+    })?;
+    wasi::gpio::tone::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl { host })?;
+    wasi::gpio::watchdog::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl {
+        host,
+    })?;
+    io_bindings::add_to_linker(linker)?;
+    v0_2_0::add_to_linker(linker)?;
+    Ok(())
+}
+
+/// Async variant of `add_to_linker`, for engines with `Config::async_support` enabled
+///
+/// Like `add_to_linker` it replaces `wasi:io/poll` and turns shadowing off on `linker` afterwards.
+pub fn add_to_linker_async<T>(linker: &mut Linker<T>) -> anyhow::Result<()>
+where
+    T: WasiGpioView + Send + 'static,
+{
+    async_bindings::wasi::gpio::delay::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| {
+        GpioImpl { host }
+    })?;
+    async_bindings::wasi::gpio::poll::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| {
+        GpioImpl { host }
+    })?;
+
+    // The other interfaces never block, so their synchronous bindings are used as is
+    wasi::gpio::general::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl {
+        host,
+    })?;
+    wasi::gpio::digital::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl {
+        host,
+    })?;
+    wasi::gpio::analog::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl { host })?;
+    wasi::gpio::counter::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl {
+        host,
+    })?;
+    wasi::gpio::encoder::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl {
+        host,
+    })?;
+    wasi::gpio::port::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl { host })?;
+    wasi::gpio::waveform::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl {
+        host,
+    })?;
+    wasi::gpio::stepper::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl {
+        host,
+    })?;
+    wasi::gpio::tone::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl { host })?;
+    wasi::gpio::watchdog::add_to_linker::<T, GpioBindingMarker<T>>(linker, |host| GpioImpl {
+        host,
+    })?;
+    io_bindings::shadow_io_poll(linker, |linker| {
+        async_bindings::io_poll::wasi::io::poll::add_to_linker::<T, GpioBindingMarker<T>>(
+            linker,
+            |host| GpioImpl { host },
+        )
+    })?;
+    v0_2_0::add_to_linker(linker)?;
     Ok(())
 }
@@ -0,0 +1,111 @@
+use super::{Mode, Policies, PolicyError, PolicyErrorKind, WasiGpioEntry};
+
+fn is_input(mode: &Mode) -> bool {
+    matches!(mode, Mode::DigitalInput | Mode::AnalogInput)
+}
+
+fn is_output(mode: &Mode) -> bool {
+    matches!(
+        mode,
+        Mode::DigitalOutput | Mode::StatefulDigitalOutput | Mode::AnalogOutput
+    )
+}
+
+/// Returns true when the entry is only ever used in one of the directions `direction` accepts
+fn only(entry: &WasiGpioEntry, direction: fn(&Mode) -> bool) -> bool {
+    !entry.modes.is_empty() && entry.modes.iter().all(direction)
+}
+
+impl Policies {
+    /// Checks the policies against the capabilities of the pins of the board, on top of what `validate` checks
+    pub fn check_board(&self) -> Vec<PolicyError> {
+        let mut errors = Vec::new();
+
+        for (index, entry) in self.wasi.gpio.iter().enumerate() {
+            let vlabel = entry.vlabel.get_ref();
+            let plabel = entry.plabel.get_ref();
+            let error = |kind| PolicyError {
+                kind,
+                span: Some(entry.plabel.span()),
+            };
+
+            // Invalid labels are already reported by `validate`
+            let Some(pin) = self.board.resolve(plabel) else {
+                continue;
+            };
+
+            if let Some(bus) = &pin.reserved {
+                errors.push(error(PolicyErrorKind::ReservedPin {
+                    vlabel: vlabel.clone(),
+                    plabel: plabel.clone(),
+                    bus: bus.clone(),
+                }));
+            }
+
+            if pin.pwm.is_none() && entry.modes.contains(&Mode::AnalogOutput) {
+                errors.push(error(PolicyErrorKind::NoHardwarePwm {
+                    vlabel: vlabel.clone(),
+                    plabel: plabel.clone(),
+                }));
+            }
+
+            // Only compared with the entries before it so every pair is reported once
+            for other in self.wasi.gpio[..index].iter() {
+                let same_pin = self
+                    .board
+                    .resolve(other.plabel.get_ref())
+                    .is_some_and(|other_pin| other_pin.bcm == pin.bcm);
+
+                if !same_pin {
+                    continue;
+                }
+
+                let (input, output) = match (only(entry, is_input), only(other, is_output)) {
+                    (true, true) => (entry, other),
+                    _ if only(entry, is_output) && only(other, is_input) => (other, entry),
+                    _ => continue,
+                };
+
+                errors.push(error(PolicyErrorKind::DirectionConflict {
+                    plabel: plabel.clone(),
+                    input: input.vlabel.get_ref().clone(),
+                    output: output.vlabel.get_ref().clone(),
+                }));
+            }
+        }
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::{BUILTIN_BOARDS, Board};
+    use crate::policies::read;
+
+    /// Policies of the demos in this repository
+    const EXAMPLES: [&str; 4] = [
+        "digital-input-output/wasm-digital-input-output/policies.toml",
+        "misc/alternate-analog-digital/policies.toml",
+        "pollables/wasm-pollables/policies.toml",
+        "pwm/wasm-pwm/policies.toml",
+    ];
+
+    #[test]
+    fn example_policies_pass_the_check_on_every_builtin_board() {
+        for example in EXAMPLES {
+            let path = format!("{}/../{example}", env!("CARGO_MANIFEST_DIR"));
+            let (mut policies, _) = read(&path).unwrap();
+
+            for board in BUILTIN_BOARDS {
+                policies.board = Board::builtin(board).unwrap();
+
+                let mut errors = policies.validate();
+                errors.extend(policies.check_board());
+                errors.retain(|error| !error.kind.is_warning());
+
+                assert!(errors.is_empty(), "{example} on {board}: {errors:?}");
+            }
+        }
+    }
+}
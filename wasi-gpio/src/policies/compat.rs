@@ -0,0 +1,442 @@
+use super::{Mode, Policies};
+
+/// Version of the `wasi:gpio` package the host implements
+const GPIO_VERSION: &str = "0.2.1";
+
+/// Interfaces of `wasi:gpio@0.2.0` the host still links, the version before 0.2.1 had no others
+const GPIO_0_2_0_INTERFACES: &[&str] = &["general", "digital", "analog", "delay", "poll"];
+
+#[derive(Debug, PartialEq)]
+pub enum ImportStatus {
+    /// Provided by the host and usable with what the policy grants
+    Granted,
+    /// Provided by the host, but the policy grants nothing the interface can use
+    Denied(String),
+    /// Not provided by the host
+    Unavailable,
+}
+
+#[derive(Debug)]
+pub struct ImportUse {
+    pub name: String,
+    pub status: ImportStatus,
+}
+
+/// What a component needs compared to what the policy grants
+#[derive(Debug)]
+pub struct CompatReport {
+    pub imports: Vec<ImportUse>,
+    /// Virtual labels of the policy that appear as string constants in the component, when scanned
+    pub referenced_labels: Option<Vec<String>>,
+    /// Virtual labels of the policy that do not appear in the component, when scanned
+    pub unreferenced_labels: Option<Vec<String>>,
+    /// Referenced virtual labels with the modes the policy grants, when no import of the component can use them
+    pub unusable_labels: Option<Vec<(String, Vec<&'static str>)>>,
+    /// String constants that the policy does not declare with the virtual label they resemble, when scanned
+    pub unknown_labels: Option<Vec<(String, String)>>,
+}
+
+impl CompatReport {
+    pub fn is_compatible(&self) -> bool {
+        self.imports
+            .iter()
+            .all(|import| import.status == ImportStatus::Granted)
+            && self
+                .unusable_labels
+                .as_ref()
+                .is_none_or(|unusable| unusable.is_empty())
+    }
+}
+
+/// Returns true when `interface` of `wasi:gpio` takes pins in `mode`
+fn uses_mode(interface: &str, mode: &Mode) -> bool {
+    match interface {
+        "digital" | "counter" | "encoder" | "waveform" | "stepper" | "tone" => matches!(
+            mode,
+            Mode::DigitalInput
+                | Mode::DigitalOutput
+                | Mode::StatefulDigitalOutput
+                | Mode::DigitalInputOutput
+        ),
+        "analog" => matches!(
+            mode,
+            Mode::AnalogInput | Mode::AnalogOutput | Mode::AnalogInputOutput
+        ),
+        _ => false,
+    }
+}
+
+/// Returns the interface of `wasi:gpio` an import refers to, when it is of a version the host implements
+fn gpio_interface(name: &str) -> Option<&str> {
+    let (path, version) = name.split_once('@').unwrap_or((name, ""));
+    let interface = path.strip_prefix("wasi:gpio/")?;
+
+    match version {
+        GPIO_VERSION => Some(interface),
+        "0.2.0" if GPIO_0_2_0_INTERFACES.contains(&interface) => Some(interface),
+        _ => None,
+    }
+}
+
+fn is_label_byte(byte: &u8) -> bool {
+    byte.is_ascii_alphanumeric() || *byte == b'_' || *byte == b'-'
+}
+
+/// Returns the runs of label characters in the data segments of every core module of the component
+fn string_constants(bytes: &[u8]) -> Vec<&[u8]> {
+    let mut constants = Vec::new();
+
+    for payload in wasmparser::Parser::new(0).parse_all(bytes) {
+        let Ok(wasmparser::Payload::DataSection(reader)) = payload else {
+            continue;
+        };
+
+        for data in reader.into_iter().flatten() {
+            constants.extend(
+                data.data
+                    .split(|byte| !is_label_byte(byte))
+                    .filter(|run| !run.is_empty()),
+            );
+        }
+    }
+
+    constants
+}
+
+/// Returns true when a label can end right before `next`, so `LED_RED` or `LED2` are not read as `LED`
+fn ends_label(next: Option<&u8>) -> bool {
+    !matches!(next, Some(byte) if byte.is_ascii_digit() || *byte == b'_' || *byte == b'-')
+}
+
+/// Returns the labels a run starts with and the rest of the run after them
+///
+/// String constants are laid out back to back in the data segments, so a label is matched where a constant can start: at the start of the run or right after another label
+fn match_labels<'r, 'l>(run: &'r [u8], labels: &[&'l String]) -> (Vec<&'l String>, &'r [u8]) {
+    let mut found = Vec::new();
+    let mut rest = run;
+
+    // The longest label wins, so `LED_RED` is not read as `LED` followed by `_RED`
+    while let Some(label) = labels
+        .iter()
+        .filter(|label| !label.is_empty() && rest.starts_with(label.as_bytes()))
+        .filter(|label| ends_label(rest.get(label.len())))
+        .max_by_key(|label| label.len())
+    {
+        found.push(*label);
+        rest = &rest[label.len()..];
+    }
+
+    (found, rest)
+}
+
+/// Returns the number of single character edits between `a` and `b`, ignoring case, a swap of neighbouring characters counts as one
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(!a[i - 1].eq_ignore_ascii_case(&b[j - 1]));
+
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+
+            if i > 1
+                && j > 1
+                && a[i - 1].eq_ignore_ascii_case(&b[j - 2])
+                && a[i - 2].eq_ignore_ascii_case(&b[j - 1])
+            {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+/// Returns the label a constant looks like a typo of, labels shorter than 3 characters match too many constants to tell
+fn resembled_label<'l>(constant: &[u8], labels: &[&'l String]) -> Option<&'l String> {
+    if constant.len() < 3 {
+        return None;
+    }
+
+    labels
+        .iter()
+        .filter(|label| label.len() >= 3)
+        .find(|label| edit_distance(constant, label.as_bytes()) <= label.len() / 4 + 1)
+        .copied()
+}
+
+impl Policies {
+    fn has_mode(&self, wanted: impl Fn(&Mode) -> bool) -> bool {
+        self.wasi
+            .gpio
+            .iter()
+            .any(|entry| entry.modes.iter().any(&wanted))
+    }
+
+    fn gpio_import_status(&self, interface: &str) -> ImportStatus {
+        let granted = match interface {
+            "general" | "delay" | "poll" => true,
+            "watchdog" if self.wasi.watchdog.is_none() => {
+                return ImportStatus::Denied("the policy has no watchdog".to_string());
+            }
+            "watchdog" => true,
+            "digital" | "counter" | "encoder" | "waveform" | "stepper" | "tone" | "analog" => {
+                self.has_mode(|mode| uses_mode(interface, mode))
+            }
+            "port" => !self.wasi.port.is_empty(),
+            _ => return ImportStatus::Unavailable,
+        };
+
+        match granted {
+            true => ImportStatus::Granted,
+            false => {
+                ImportStatus::Denied(format!("the policy grants no pin usable by `{interface}`"))
+            }
+        }
+    }
+
+    fn import_status(&self, name: &str) -> ImportStatus {
+        let (path, _) = name.split_once('@').unwrap_or((name, ""));
+
+        if path.starts_with("wasi:gpio/") {
+            return match gpio_interface(name) {
+                Some(interface) => self.gpio_import_status(interface),
+                None => ImportStatus::Unavailable,
+            };
+        }
+
+        let network = &self.wasi.network;
+        match path {
+            "wasi:sockets/ip-name-lookup" if !network.ip_name_lookup => {
+                ImportStatus::Denied("the policy does not allow ip-name-lookup".to_string())
+            }
+            "wasi:sockets/tcp" | "wasi:sockets/udp" if network.allow.is_empty() => {
+                ImportStatus::Denied("the policy grants no network addresses".to_string())
+            }
+            "wasi:filesystem/preopens" if self.wasi.dir.is_empty() => {
+                ImportStatus::Denied("the policy grants no directories".to_string())
+            }
+            // Everything else is left to the linker, which reports what wasmtime-wasi does not provide
+            _ => ImportStatus::Granted,
+        }
+    }
+
+    /// Compares the imports of a component with the policy, the string constants in `bytes` are compared with the virtual labels when given
+    pub fn check_component(
+        &self,
+        engine: &wasmtime::Engine,
+        component: &wasmtime::component::Component,
+        bytes: Option<&[u8]>,
+    ) -> CompatReport {
+        let imports: Vec<ImportUse> = component
+            .component_type()
+            .imports(engine)
+            .map(|(name, _)| ImportUse {
+                name: name.to_string(),
+                status: self.import_status(name),
+            })
+            .collect();
+
+        let Some(bytes) = bytes else {
+            return CompatReport {
+                imports,
+                referenced_labels: None,
+                unreferenced_labels: None,
+                unusable_labels: None,
+                unknown_labels: None,
+            };
+        };
+
+        let labels: Vec<&String> = self
+            .wasi
+            .gpio
+            .iter()
+            .map(|entry| entry.vlabel.get_ref())
+            .chain(self.wasi.port.iter().map(|port| port.vlabel.get_ref()))
+            .collect();
+
+        // A label the component passes as `pin-label` ends up as a string constant in a data segment
+        let mut found = Vec::new();
+        let mut unknown: Vec<(String, String)> = Vec::new();
+        for run in string_constants(bytes) {
+            let (matched, rest) = match_labels(run, &labels);
+
+            for label in matched {
+                if !found.contains(&label) {
+                    found.push(label);
+                }
+            }
+
+            let constant = String::from_utf8_lossy(rest).to_string();
+            if let Some(label) = resembled_label(rest, &labels)
+                && !unknown.iter().any(|(known, _)| *known == constant)
+            {
+                unknown.push((constant, label.clone()));
+            }
+        }
+
+        let (referenced, unreferenced): (Vec<&String>, Vec<&String>) =
+            labels.iter().partition(|label| found.contains(label));
+
+        let interfaces: Vec<&str> = imports
+            .iter()
+            .filter_map(|import| gpio_interface(&import.name))
+            .collect();
+
+        let mut unusable = Vec::new();
+        for label in referenced.iter() {
+            let (usable, modes) = match self.find(label) {
+                Some(entry) => (
+                    entry.modes.iter().any(|mode| {
+                        interfaces
+                            .iter()
+                            .any(|interface| uses_mode(interface, mode))
+                    }),
+                    entry.modes.iter().map(Mode::name).collect(),
+                ),
+                None => (interfaces.contains(&"port"), vec!["port"]),
+            };
+
+            if !usable {
+                unusable.push((label.to_string(), modes));
+            }
+        }
+
+        CompatReport {
+            imports,
+            referenced_labels: Some(referenced.into_iter().cloned().collect()),
+            unreferenced_labels: Some(unreferenced.into_iter().cloned().collect()),
+            unusable_labels: Some(unusable),
+            unknown_labels: Some(unknown),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::component::Component;
+
+    const POLICY: &str = r#"
+        [[wasi.gpio]]
+        vlabel = "OUT"
+        modes = ["digital-output"]
+        plabel = "GPIO17"
+
+        [[wasi.gpio]]
+        vlabel = "IN"
+        modes = ["digital-input"]
+        plabel = "GPIO27"
+
+        [[wasi.gpio]]
+        vlabel = "HEATER"
+        modes = ["analog-output"]
+        plabel = "GPIO18"
+    "#;
+
+    /// Checks a component whose only core module has `data` in a data segment
+    fn check(data: &str) -> CompatReport {
+        let policies: Policies = toml::from_str(POLICY).unwrap();
+        let engine = wasmtime::Engine::default();
+        let bytes = wat::parse_str(format!(
+            r#"(component
+                (import "wasi:gpio/digital@0.2.0" (instance))
+                (core module
+                    (memory 1)
+                    (data (i32.const 0) "{data}")))"#
+        ))
+        .unwrap();
+        let component = Component::new(&engine, &bytes).unwrap();
+
+        policies.check_component(&engine, &component, Some(&bytes))
+    }
+
+    fn referenced(report: &CompatReport) -> Vec<&str> {
+        let mut labels: Vec<&str> = report
+            .referenced_labels
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        labels.sort();
+        labels
+    }
+
+    #[test]
+    fn finds_labels_packed_together() {
+        assert_eq!(referenced(&check("OUTIN")), ["IN", "OUT"]);
+    }
+
+    #[test]
+    fn finds_labels_packed_with_other_constants() {
+        assert_eq!(referenced(&check("OUTINPWM")), ["IN", "OUT"]);
+        assert_eq!(
+            referenced(&check("OUTINcalled `Option::unwrap()` on a `None` value")),
+            ["IN", "OUT"]
+        );
+        assert_eq!(referenced(&check("invalid pin\\00OUT")), ["OUT"]);
+    }
+
+    #[test]
+    fn ignores_labels_inside_longer_names() {
+        assert!(referenced(&check("OUT_2 TIMEOUT IN2 LED-IN")).is_empty());
+    }
+
+    #[test]
+    fn reports_labels_the_policy_does_not_declare() {
+        let report = check("HAETER OTU INOUT");
+
+        assert_eq!(
+            report.unknown_labels.unwrap(),
+            [
+                ("HAETER".to_string(), "HEATER".to_string()),
+                ("OTU".to_string(), "OUT".to_string()),
+            ]
+        );
+        assert_eq!(referenced(&check("HAETER OTU INOUT")), ["IN", "OUT"]);
+    }
+
+    #[test]
+    fn reports_labels_granted_in_modes_no_import_uses() {
+        let report = check("HEATER");
+
+        assert_eq!(
+            report.unusable_labels.unwrap(),
+            [("HEATER".to_string(), vec!["analog-output"])]
+        );
+        assert!(check("OUT").unusable_labels.unwrap().is_empty());
+    }
+
+    #[test]
+    fn does_not_scan_without_bytes() {
+        let policies: Policies = toml::from_str(POLICY).unwrap();
+        let engine = wasmtime::Engine::default();
+        let component = Component::new(&engine, "(component)").unwrap();
+        let report = policies.check_component(&engine, &component, None);
+
+        assert!(report.referenced_labels.is_none());
+        assert!(report.unknown_labels.is_none());
+        assert!(report.is_compatible());
+    }
+
+    #[test]
+    fn knows_the_interfaces_of_both_versions() {
+        assert_eq!(gpio_interface("wasi:gpio/digital@0.2.1"), Some("digital"));
+        assert_eq!(gpio_interface("wasi:gpio/digital@0.2.0"), Some("digital"));
+        assert_eq!(gpio_interface("wasi:gpio/tone@0.2.1"), Some("tone"));
+        assert_eq!(gpio_interface("wasi:gpio/tone@0.2.0"), None);
+        assert_eq!(gpio_interface("wasi:gpio/analog@0.2.0"), Some("analog"));
+        assert_eq!(gpio_interface("wasi:gpio/digital@0.3.0"), None);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::wasi::gpio::{analog, digital, general};
+
+/// A flag a component passes when getting a pin, as written in the policy file
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Flag {
+    ActiveHigh,
+    ActiveLow,
+    PullUp,
+    PullDown,
+    Active,
+    Inactive,
+    Pwm,
+    Servo,
+}
+
+impl Flag {
+    fn digital(&self) -> Option<digital::DigitalFlag> {
+        match self {
+            Flag::ActiveHigh => Some(digital::DigitalFlag::ACTIVE_HIGH),
+            Flag::ActiveLow => Some(digital::DigitalFlag::ACTIVE_LOW),
+            Flag::PullUp => Some(digital::DigitalFlag::PULL_UP),
+            Flag::PullDown => Some(digital::DigitalFlag::PULL_DOWN),
+            Flag::Active => Some(digital::DigitalFlag::ACTIVE),
+            Flag::Inactive => Some(digital::DigitalFlag::INACTIVE),
+            Flag::Pwm | Flag::Servo => None,
+        }
+    }
+
+    fn analog(&self) -> Option<analog::AnalogFlag> {
+        match self {
+            Flag::Pwm => Some(analog::AnalogFlag::PWM),
+            Flag::Servo => Some(analog::AnalogFlag::SERVO),
+            _ => None,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ActiveLevel {
+    ActiveHigh,
+    ActiveLow,
+}
+
+impl From<ActiveLevel> for general::ActiveLevel {
+    fn from(value: ActiveLevel) -> Self {
+        match value {
+            ActiveLevel::ActiveHigh => Self::ActiveHigh,
+            ActiveLevel::ActiveLow => Self::ActiveLow,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum InitialState {
+    Active,
+    Inactive,
+}
+
+impl From<InitialState> for digital::PinState {
+    fn from(value: InitialState) -> Self {
+        match value {
+            InitialState::Active => Self::Active,
+            InitialState::Inactive => Self::Inactive,
+        }
+    }
+}
+
+/// Limits on how a component may configure and drive a pin, enforced whatever the component asks for
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct PinConstraints {
+    /// Flags the component may not pass when getting the pin
+    #[serde(default)]
+    pub denied_flags: Vec<Flag>,
+    /// Active level the pin always gets, passing the other one is an error
+    #[serde(default)]
+    pub active_level: Option<ActiveLevel>,
+    /// State an output starts in, passing the other one is an error
+    #[serde(default)]
+    pub initial_state: Option<InitialState>,
+    /// Highest duty cycle of a PWM output from 0 to 1, higher values are clamped
+    #[serde(default)]
+    pub max_duty_cycle: Option<f64>,
+    #[serde(default)]
+    pub min_frequency_hz: Option<f64>,
+    #[serde(default)]
+    pub max_frequency_hz: Option<f64>,
+}
+
+impl PinConstraints {
+    pub fn denies_digital(&self, flags: &[digital::DigitalFlag]) -> bool {
+        self.denied_flags
+            .iter()
+            .filter_map(Flag::digital)
+            .any(|denied| flags.contains(&denied))
+    }
+
+    pub fn denies_analog(&self, flags: &[analog::AnalogFlag]) -> bool {
+        self.denied_flags
+            .iter()
+            .filter_map(Flag::analog)
+            .any(|denied| flags.contains(&denied))
+    }
+
+    pub fn allows_frequency(&self, frequency: f64) -> bool {
+        self.min_frequency_hz.is_none_or(|min| frequency >= min)
+            && self.max_frequency_hz.is_none_or(|max| frequency <= max)
+    }
+
+    /// Returns the duty cycle that is actually driven when `duty_cycle` is asked for
+    pub fn limit_duty_cycle(&self, duty_cycle: f64) -> f64 {
+        match self.max_duty_cycle {
+            Some(max) => duty_cycle.min(max),
+            None => duty_cycle,
+        }
+    }
+
+    /// Returns true when the policy puts no constraint on the pin
+    pub fn is_empty(&self) -> bool {
+        self.denied_flags.is_empty()
+            && self.active_level.is_none()
+            && self.initial_state.is_none()
+            && self.max_duty_cycle.is_none()
+            && self.min_frequency_hz.is_none()
+            && self.max_frequency_hz.is_none()
+    }
+
+    /// Returns a reason when the constraints contradict themselves
+    pub fn check(&self) -> Option<String> {
+        if let Some(max) = self.max_duty_cycle
+            && !(0. ..=1.).contains(&max)
+        {
+            return Some("max-duty-cycle needs to be between 0 and 1".to_string());
+        }
+
+        if let (Some(min), Some(max)) = (self.min_frequency_hz, self.max_frequency_hz)
+            && min > max
+        {
+            return Some("min-frequency-hz needs to be at most max-frequency-hz".to_string());
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constraints(source: &str) -> PinConstraints {
+        toml::from_str(source).unwrap()
+    }
+
+    #[test]
+    fn clamps_the_duty_cycle_to_the_max_duty_cycle() {
+        let constraints = constraints("max-duty-cycle = 0.25");
+
+        assert_eq!(constraints.limit_duty_cycle(0.1), 0.1);
+        assert_eq!(constraints.limit_duty_cycle(0.25), 0.25);
+        assert_eq!(constraints.limit_duty_cycle(1.), 0.25);
+    }
+
+    #[test]
+    fn limits_the_duty_cycle_of_every_label_of_a_physical_pin() {
+        let policies: crate::policies::Policies = toml::from_str(
+            r#"
+            [[wasi.gpio]]
+            vlabel = "HEATER"
+            modes = ["analog-output"]
+            plabel = "GPIO18"
+            constraints = { max-duty-cycle = 0.6 }
+
+            [[wasi.gpio]]
+            vlabel = "HEATER_TONE"
+            modes = ["digital-output"]
+            plabel = "PIN12"
+            constraints = { max-duty-cycle = 0.8, min-frequency-hz = 100 }
+            "#,
+        )
+        .unwrap();
+
+        for vlabel in ["HEATER", "HEATER_TONE"] {
+            let constraints = policies.get_constraints(vlabel).unwrap();
+
+            assert_eq!(constraints.max_duty_cycle, Some(0.6), "{vlabel}");
+            assert!(policies.is_duty_limited(vlabel), "{vlabel}");
+        }
+
+        // Only the duty cycle is shared, the other constraints stay with their label
+        assert_eq!(
+            policies
+                .get_constraints("HEATER_TONE")
+                .unwrap()
+                .min_frequency_hz,
+            Some(100.)
+        );
+        assert_eq!(
+            policies.get_constraints("HEATER").unwrap().min_frequency_hz,
+            None
+        );
+    }
+
+    #[test]
+    fn leaves_the_duty_cycle_without_a_max_duty_cycle() {
+        assert_eq!(PinConstraints::default().limit_duty_cycle(1.), 1.);
+    }
+
+    #[test]
+    fn allows_frequencies_between_min_and_max() {
+        let constraints = constraints(
+            r#"
+            min-frequency-hz = 50
+            max-frequency-hz = 1000
+            "#,
+        );
+
+        assert!(!constraints.allows_frequency(49.9));
+        assert!(constraints.allows_frequency(50.));
+        assert!(constraints.allows_frequency(1000.));
+        assert!(!constraints.allows_frequency(1000.1));
+    }
+
+    #[test]
+    fn allows_frequencies_on_the_open_side() {
+        let min = constraints("min-frequency-hz = 50");
+        let max = constraints("max-frequency-hz = 1000");
+
+        assert!(min.allows_frequency(1e6));
+        assert!(!min.allows_frequency(1.));
+        assert!(max.allows_frequency(0.1));
+        assert!(!max.allows_frequency(1e6));
+    }
+
+    #[test]
+    fn refuses_contradicting_constraints() {
+        assert!(constraints("max-duty-cycle = 1.5").check().is_some());
+        assert!(constraints("max-duty-cycle = -0.1").check().is_some());
+        assert!(
+            constraints(
+                r#"
+                min-frequency-hz = 1000
+                max-frequency-hz = 50
+                "#
+            )
+            .check()
+            .is_some()
+        );
+
+        assert!(constraints("max-duty-cycle = 0").check().is_none());
+        assert!(constraints("max-duty-cycle = 1").check().is_none());
+        assert!(PinConstraints::default().check().is_none());
+    }
+
+    #[test]
+    fn denies_the_flags_of_the_matching_kind() {
+        let constraints = constraints(r#"denied-flags = ["pull-up", "servo"]"#);
+
+        assert!(constraints.denies_digital(&[digital::DigitalFlag::PULL_UP]));
+        assert!(!constraints.denies_digital(&[digital::DigitalFlag::PULL_DOWN]));
+        assert!(constraints.denies_analog(&[analog::AnalogFlag::SERVO]));
+        assert!(!constraints.denies_analog(&[analog::AnalogFlag::PWM]));
+    }
+}
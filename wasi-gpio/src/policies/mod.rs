@@ -0,0 +1,644 @@
+use crate::board::{Board, BoardPin};
+use anyhow::Context;
+
+mod check;
+mod compat;
+mod constraints;
+mod validate;
+
+pub use compat::{CompatReport, ImportStatus, ImportUse};
+pub use constraints::{ActiveLevel, Flag, InitialState, PinConstraints};
+pub use validate::{PolicyError, PolicyErrorKind, PolicyErrors, load, read};
+
+#[derive(clap::Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Config {
+    #[command(subcommand)]
+    pub command: Option<HostCommand>,
+
+    #[arg(short, long, required = true)]
+    pub policy_file: Option<String>,
+
+    #[arg(short, long, required = true)]
+    pub component: Option<String>,
+
+    /// Board the physical labels refer to, one of rpi3, rpi4, rpi5, zero, cm4 or a board description file, detected when left out
+    #[arg(long)]
+    pub board: Option<String>,
+
+    /// Delays get spun instead of slept for their last part, in microseconds
+    #[arg(long, default_value_t = 200)]
+    pub spin_threshold_us: u64,
+
+    /// Run the component with realtime scheduling once it uses a delay, requires privileges
+    #[arg(long)]
+    pub realtime: bool,
+
+    /// Print the measured accuracy of the delays when the component finishes
+    #[arg(long)]
+    pub delay_report: bool,
+
+    /// Stop the component after running for this many milliseconds
+    #[arg(long)]
+    pub timeout_ms: Option<u64>,
+
+    /// World the component was built for
+    #[arg(long, value_enum, default_value_t = World::Auto)]
+    pub world: World,
+
+    /// Call this `func()` export instead of the entry point of the world, use `interface#function` for exports of an interface
+    #[arg(long)]
+    pub invoke: Option<String>,
+
+    /// Environment variable for the guest, can be given multiple times
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_var)]
+    pub env: Vec<(String, String)>,
+
+    /// Directory the guest can read and write, mounted at GUEST or at the same path, it has to be inside a directory the policy grants
+    #[arg(long = "dir", value_name = "HOST[::GUEST]")]
+    pub dirs: Vec<String>,
+
+    /// Directory the guest can only read, mounted at GUEST or at the same path, it has to be inside a directory the policy grants
+    #[arg(long = "dir-ro", value_name = "HOST[::GUEST]")]
+    pub read_only_dirs: Vec<String>,
+
+    /// Arguments for the guest, the component path is passed as the first one
+    #[arg(last = true)]
+    pub args: Vec<String>,
+}
+
+fn parse_env_var(var: &str) -> Result<(String, String), String> {
+    var.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{var}`"))
+}
+
+/// A host directory made available to the guest
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Preopen {
+    pub host: String,
+    pub guest: String,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+impl Preopen {
+    fn parse(dir: &str, read_only: bool) -> Self {
+        let (host, guest) = dir.split_once("::").unwrap_or((dir, dir));
+
+        Self {
+            host: host.to_string(),
+            guest: guest.to_string(),
+            read_only,
+        }
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum HostCommand {
+    /// Validate a policy file against the header of the board and print the resolved pins, without running a component
+    CheckPolicy {
+        policy_file: String,
+
+        /// Board the physical labels refer to, detected when left out
+        #[arg(long)]
+        board: Option<String>,
+    },
+
+    /// Report which imports and virtual labels a component needs that the policy does not grant
+    CheckComponent {
+        #[arg(short, long)]
+        policy_file: String,
+
+        #[arg(short, long)]
+        component: String,
+
+        /// Board the physical labels refer to, detected when left out
+        #[arg(long)]
+        board: Option<String>,
+
+        /// Also look for the virtual labels of the policy, and misspellings of them, in the string constants of the component
+        #[arg(long)]
+        scan_labels: bool,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum World {
+    /// Run `wasi:cli/run` when the component exports it, `start` otherwise
+    Auto,
+    /// Call the `start` export of the `rpi` world from `guest.wit`
+    Rpi,
+    /// Run the component as a `wasi:cli/run` command, like a `wasm32-wasip2` binary with a `main`
+    Command,
+}
+
+#[derive(serde::Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    DigitalInput,
+    DigitalOutput,
+    StatefulDigitalOutput,
+    DigitalInputOutput,
+    AnalogInput,
+    AnalogOutput,
+    AnalogInputOutput,
+}
+
+impl Mode {
+    /// Name of the mode as written in the policy file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::DigitalInput => "digital-input",
+            Mode::DigitalOutput => "digital-output",
+            Mode::StatefulDigitalOutput => "stateful-digital-output",
+            Mode::DigitalInputOutput => "digital-input-output",
+            Mode::AnalogInput => "analog-input",
+            Mode::AnalogOutput => "analog-output",
+            Mode::AnalogInputOutput => "analog-input-output",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub struct ServoLimits {
+    pub min_pulse_us: u32,
+    pub max_pulse_us: u32,
+}
+
+impl Default for ServoLimits {
+    fn default() -> Self {
+        Self {
+            min_pulse_us: 1000,
+            max_pulse_us: 2000,
+        }
+    }
+}
+
+/// Timing of a stepper driver, taken from the policy of its DIR pin
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub struct StepperTiming {
+    /// Time the DIR pin is given to settle before the next STEP pulse after it changed
+    pub dir_setup_us: u64,
+}
+
+impl Default for StepperTiming {
+    /// Covers the 200 ns of the A4988 up to the 5 µs some TMC drivers need
+    fn default() -> Self {
+        Self { dir_setup_us: 5 }
+    }
+}
+
+/// What happens to an output change that exceeds the rate limit of the pin
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnRateExceeded {
+    /// The call fails with `rate-limited` and the output stays as it is
+    #[default]
+    Error,
+    /// The call blocks until the change is allowed, it sleeps the thread of the store also under the async bindings
+    ///
+    /// It fails when the host stops the component while it waits, the change is not written then
+    Delay,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub struct RateLimit {
+    /// Most changes of the output within any second
+    #[serde(default)]
+    pub max_changes_per_second: Option<u32>,
+    /// Shortest time the output keeps a state or value before it may change again
+    #[serde(default)]
+    pub min_hold_ms: Option<u64>,
+    #[serde(default)]
+    pub on_exceeded: OnRateExceeded,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct WasiGpioEntry {
+    pub vlabel: toml::Spanned<String>,
+    pub modes: Vec<Mode>,
+    pub plabel: toml::Spanned<String>,
+    /// Pulse range used when the pin drives an RC servo
+    #[serde(default)]
+    pub servo: Option<ServoLimits>,
+    /// Timing of the stepper driver when the pin is used as its DIR pin
+    #[serde(default)]
+    pub stepper: Option<StepperTiming>,
+    #[serde(default)]
+    pub constraints: PinConstraints,
+    /// Limits how often the output may change, to protect hardware like relay contacts
+    #[serde(default, rename = "rate-limit")]
+    pub rate_limit: Option<RateLimit>,
+    /// State the pin is left in when its resource is dropped, the component traps or the host exits
+    #[serde(default, rename = "safe-state")]
+    pub safe_state: Option<SafeState>,
+}
+
+/// State a pin is put in once the component stops driving it
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SafeState {
+    /// Driven low
+    Low,
+    /// Driven high
+    High,
+    /// Input without pull resistor
+    Float,
+    /// Left at the mode and level it had
+    Hold,
+}
+
+/// Physical level that switches on what a pin drives
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnLevel {
+    #[default]
+    High,
+    Low,
+}
+
+impl From<OnLevel> for rppal::gpio::Level {
+    fn from(value: OnLevel) -> Self {
+        match value {
+            OnLevel::High => Self::High,
+            OnLevel::Low => Self::Low,
+        }
+    }
+}
+
+/// Pins of which at most one may be switched on at a time, like the sides of an H-bridge
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Interlock {
+    /// Virtual labels of the interlocked pins
+    pub pins: Vec<toml::Spanned<String>>,
+    #[serde(default)]
+    pub on_level: OnLevel,
+    /// Time between one pin switching off and another switching on
+    #[serde(default)]
+    pub dead_time_us: u64,
+}
+
+/// Heartbeat the component has to give, the host stops it when it hangs
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub struct Watchdog {
+    /// Time the component has to call `kick` within, counted from its start and every kick
+    pub interval_ms: u64,
+    /// How often the host restarts the component after the watchdog expired, it is not restarted by default
+    #[serde(default)]
+    pub restarts: u32,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct WasiGpioPort {
+    pub vlabel: toml::Spanned<String>,
+    pub modes: Vec<Mode>,
+    /// Virtual labels of the pins in the port, the first pin is bit 0
+    pub pins: Vec<toml::Spanned<String>>,
+}
+
+#[derive(serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct WasiNetwork {
+    /// Addresses the component may connect to or bind, an unspecified ip or a port of 0 matches any
+    #[serde(default)]
+    pub allow: Vec<std::net::SocketAddr>,
+    #[serde(default)]
+    pub ip_name_lookup: bool,
+}
+
+impl WasiNetwork {
+    fn is_allowed(&self, addr: std::net::SocketAddr) -> bool {
+        self.allow.iter().any(|allowed| {
+            (allowed.ip().is_unspecified() || allowed.ip() == addr.ip())
+                && (allowed.port() == 0 || allowed.port() == addr.port())
+        })
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct WasiClocks {
+    /// Without it the wall clock stays at the unix epoch, the monotonic clock is always available
+    #[serde(default)]
+    pub wall: bool,
+}
+
+/// Wall clock for components that were not granted the real time of day
+struct EpochWallClock;
+
+impl wasmtime_wasi::HostWallClock for EpochWallClock {
+    fn resolution(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(1)
+    }
+
+    fn now(&self) -> std::time::Duration {
+        std::time::Duration::ZERO
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Wasi {
+    pub gpio: Vec<WasiGpioEntry>,
+    #[serde(default)]
+    pub port: Vec<WasiGpioPort>,
+    #[serde(default)]
+    pub interlock: Vec<Interlock>,
+    #[serde(default)]
+    pub watchdog: Option<Watchdog>,
+    /// Network access, denied when left out
+    #[serde(default)]
+    pub network: WasiNetwork,
+    /// Directories made available to the component
+    #[serde(default)]
+    pub dir: Vec<Preopen>,
+    #[serde(default)]
+    pub clocks: WasiClocks,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct Policies {
+    pub wasi: Wasi,
+    /// Board the physical labels are resolved on, set when the policy file is loaded
+    #[serde(skip)]
+    pub board: Board,
+}
+
+impl Config {
+    pub fn get_board(&self) -> anyhow::Result<Board> {
+        Board::from_arg(self.board.as_deref())
+    }
+
+    pub fn get_policies(&self, board: Board) -> Result<Policies, PolicyErrors> {
+        load(self.policy_file.as_deref().unwrap_or_default(), board)
+    }
+
+    pub fn get_component_path(&self) -> &str {
+        self.component.as_deref().unwrap_or_default()
+    }
+
+    pub fn get_timeout(&self) -> Option<std::time::Duration> {
+        self.timeout_ms.map(std::time::Duration::from_millis)
+    }
+
+    pub fn get_preopens(&self) -> Vec<Preopen> {
+        let read_write = self.dirs.iter().map(|dir| Preopen::parse(dir, false));
+        let read_only = self
+            .read_only_dirs
+            .iter()
+            .map(|dir| Preopen::parse(dir, true));
+
+        read_write.chain(read_only).collect()
+    }
+
+    pub fn get_delay(&self) -> crate::delay::Delay {
+        crate::delay::Delay::new(
+            std::time::Duration::from_micros(self.spin_threshold_us),
+            self.realtime,
+        )
+    }
+}
+
+impl Preopen {
+    pub fn grant(&self, builder: &mut wasmtime_wasi::WasiCtxBuilder) -> anyhow::Result<()> {
+        let (dir_perms, file_perms) = match self.read_only {
+            true => (
+                wasmtime_wasi::DirPerms::READ,
+                wasmtime_wasi::FilePerms::READ,
+            ),
+            false => (
+                wasmtime_wasi::DirPerms::all(),
+                wasmtime_wasi::FilePerms::all(),
+            ),
+        };
+
+        builder
+            .preopened_dir(&self.host, &self.guest, dir_perms, file_perms)
+            .with_context(|| format!("failed to open directory `{}`", self.host))?;
+        Ok(())
+    }
+}
+
+impl Policies {
+    /// Grants the network, filesystem and clock capabilities declared in the policy, everything else stays denied
+    pub fn grant_capabilities(
+        &self,
+        builder: &mut wasmtime_wasi::WasiCtxBuilder,
+    ) -> anyhow::Result<()> {
+        let network = std::sync::Arc::new(self.wasi.network.clone());
+        builder
+            .socket_addr_check(move |addr, _| {
+                let allowed = network.is_allowed(addr);
+                Box::pin(async move { allowed })
+            })
+            .allow_ip_name_lookup(self.wasi.network.ip_name_lookup);
+
+        for preopen in self.wasi.dir.iter() {
+            preopen.grant(builder)?;
+        }
+
+        if !self.wasi.clocks.wall {
+            builder.wall_clock(EpochWallClock);
+        }
+
+        Ok(())
+    }
+
+    /// Fails unless `preopen` is inside a directory of the policy, one that is writable when `preopen` is
+    ///
+    /// Directories from the command line can only narrow what the policy grants, paths are compared once symlinks are resolved
+    pub fn check_preopen(&self, preopen: &Preopen) -> anyhow::Result<()> {
+        let host = std::fs::canonicalize(&preopen.host)
+            .with_context(|| format!("failed to open directory `{}`", preopen.host))?;
+
+        let containing: Vec<&Preopen> = self
+            .wasi
+            .dir
+            .iter()
+            .filter(|dir| {
+                std::fs::canonicalize(&dir.host).is_ok_and(|granted| host.starts_with(granted))
+            })
+            .collect();
+
+        if containing.is_empty() {
+            anyhow::bail!(
+                "directory `{}` is not inside a directory the policy grants",
+                preopen.host
+            );
+        }
+
+        if !preopen.read_only && containing.iter().all(|dir| dir.read_only) {
+            anyhow::bail!(
+                "directory `{}` is only granted read-only by the policy, use --dir-ro",
+                preopen.host
+            );
+        }
+
+        Ok(())
+    }
+
+    fn find(&self, vlabel: &str) -> Option<&WasiGpioEntry> {
+        for entry in self.wasi.gpio.iter() {
+            if vlabel.eq(entry.vlabel.get_ref()) {
+                return Some(entry);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the entries that use the same physical pin as `vlabel`, `vlabel` included
+    fn aliases(&self, vlabel: &str) -> Vec<&WasiGpioEntry> {
+        let Some(bcm) = self.get_pin(vlabel).map(|pin| pin.bcm) else {
+            return self.find(vlabel).into_iter().collect();
+        };
+
+        self.wasi
+            .gpio
+            .iter()
+            .filter(|entry| {
+                self.board
+                    .resolve(entry.plabel.get_ref())
+                    .is_some_and(|pin| pin.bcm == bcm)
+            })
+            .collect()
+    }
+
+    /// Returns the board pin the physical label of a virtual label refers to
+    pub fn get_pin(&self, vlabel: &str) -> Option<&BoardPin> {
+        self.board.resolve(self.find(vlabel)?.plabel.get_ref())
+    }
+
+    /// Returns true when both virtual labels resolve to the same physical pin
+    pub fn is_same_pin(&self, vlabel: &str, other: &str) -> bool {
+        match (self.get_pin(vlabel), self.get_pin(other)) {
+            (Some(pin), Some(other)) => pin.bcm == other.bcm,
+            _ => vlabel == other,
+        }
+    }
+
+    fn find_port(&self, vlabel: &str) -> Option<&WasiGpioPort> {
+        self.wasi
+            .port
+            .iter()
+            .find(|port| vlabel.eq(port.vlabel.get_ref()))
+    }
+
+    /// Returns the BCM numbers of the pins in a port, bit 0 first
+    pub fn get_port_pins(&self, vlabel: &str) -> Option<Vec<u8>> {
+        self.find_port(vlabel)?
+            .pins
+            .iter()
+            .map(|pin| self.get_pin(pin.get_ref()).map(|pin| pin.bcm))
+            .collect()
+    }
+
+    pub fn is_port_mode_allowed(&self, vlabel: &str, mode: Mode) -> bool {
+        match self.find_port(vlabel) {
+            Some(port) => port.modes.contains(&mode),
+            None => false,
+        }
+    }
+
+    pub fn get_servo_limits(&self, vlabel: &str) -> Option<ServoLimits> {
+        self.find(vlabel)
+            .map(|entry| entry.servo.unwrap_or_default())
+    }
+
+    pub fn get_stepper_timing(&self, vlabel: &str) -> Option<StepperTiming> {
+        self.find(vlabel)
+            .map(|entry| entry.stepper.unwrap_or_default())
+    }
+
+    /// Returns the constraints of `vlabel`, with the lowest max-duty-cycle of the labels on its physical pin
+    pub fn get_constraints(&self, vlabel: &str) -> Option<PinConstraints> {
+        let mut constraints = self.find(vlabel)?.constraints.clone();
+
+        constraints.max_duty_cycle = self
+            .aliases(vlabel)
+            .into_iter()
+            .filter_map(|entry| entry.constraints.max_duty_cycle)
+            .reduce(f64::min);
+
+        Some(constraints)
+    }
+
+    /// Returns the strictest rate limit of the labels on the physical pin of `vlabel`, changes are counted per physical pin
+    pub fn get_rate_limit(&self, vlabel: &str) -> Option<RateLimit> {
+        self.aliases(vlabel)
+            .into_iter()
+            .filter_map(|entry| entry.rate_limit)
+            .reduce(RateLimit::strictest)
+    }
+
+    pub fn get_safe_state(&self, vlabel: &str) -> Option<SafeState> {
+        self.find(vlabel)?.safe_state
+    }
+
+    /// Returns the virtual labels of the pins in a port
+    pub fn get_port_labels(&self, vlabel: &str) -> Option<Vec<&str>> {
+        Some(
+            self.find_port(vlabel)?
+                .pins
+                .iter()
+                .map(|pin| pin.get_ref().as_str())
+                .collect(),
+        )
+    }
+
+    /// Returns true when writes to the pin go through a rate limit or an interlock, which waveforms, steppers, tones and ports bypass
+    pub fn is_output_guarded(&self, vlabel: &str) -> bool {
+        self.get_rate_limit(vlabel).is_some() || self.is_interlocked(vlabel)
+    }
+
+    /// Returns true when the pin has a max-duty-cycle below 1, only analog outputs and tones can keep to it
+    pub fn is_duty_limited(&self, vlabel: &str) -> bool {
+        self.get_constraints(vlabel)
+            .and_then(|constraints| constraints.max_duty_cycle)
+            .is_some_and(|max| max < 1.)
+    }
+
+    /// Returns true when an interlock lists `vlabel` or another label of its physical pin
+    pub fn is_interlocked(&self, vlabel: &str) -> bool {
+        self.aliases(vlabel).iter().any(|entry| {
+            self.wasi.interlock.iter().any(|interlock| {
+                interlock
+                    .pins
+                    .iter()
+                    .any(|pin| pin.get_ref() == entry.vlabel.get_ref())
+            })
+        })
+    }
+
+    /// Returns true when a pin of the port is guarded or constrained, a port is configured as a whole and can not apply either per pin
+    pub fn is_port_guarded(&self, vlabel: &str) -> bool {
+        self.find_port(vlabel).is_some_and(|port| {
+            port.pins.iter().any(|pin| {
+                self.is_output_guarded(pin.get_ref())
+                    || self
+                        .get_constraints(pin.get_ref())
+                        .is_some_and(|constraints| !constraints.is_empty())
+            })
+        })
+    }
+
+    pub fn is_mode_allowed(&self, vlabel: &str, mode: Mode) -> bool {
+        let entry = match self.find(vlabel) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        for allowed_mode in &entry.modes {
+            if mode == *allowed_mode {
+                return true;
+            }
+        }
+
+        false
+    }
+}
@@ -0,0 +1,757 @@
+use super::{Mode, OnLevel, Policies, SafeState, WasiGpioEntry};
+use crate::analog::implementations::SERVO_PERIOD;
+use crate::board::Board;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+#[derive(Debug)]
+pub enum PolicyErrorKind {
+    Read(std::io::Error),
+    Parse(String),
+    DuplicateVlabel(String),
+    /// The physical label names no pin of the board
+    InvalidPlabel {
+        plabel: String,
+        board: String,
+    },
+    /// The physical pin is already used in the same mode by the entry with the given virtual label
+    PlabelInUse {
+        plabel: String,
+        vlabel: String,
+    },
+    AnalogInputUnsupported {
+        vlabel: String,
+        plabel: String,
+    },
+    UnknownPortPin {
+        port: String,
+        pin: String,
+    },
+    InvalidServoLimits(String),
+    UnknownInterlockPin(String),
+    /// An interlock lists fewer than two pins, so it has nothing to keep apart
+    InterlockTooSmall,
+    /// The safe state of an interlocked pin is its on level or holds it, so it can stay on next to another pin
+    InterlockedSafeState(String),
+    /// The entry drives an interlocked physical pin with PWM, which switches it on whatever the other pins do
+    InterlockedPwm {
+        vlabel: String,
+        plabel: String,
+    },
+    InvalidRateLimit(String),
+    InvalidWatchdog,
+    InvalidConstraints {
+        vlabel: String,
+        reason: String,
+    },
+    /// Used as an input by one virtual label and as an output by the other
+    DirectionConflict {
+        plabel: String,
+        input: String,
+        output: String,
+    },
+    /// The pin belongs to a bus of the board, it is only free while the bus is disabled so this is a warning
+    ReservedPin {
+        vlabel: String,
+        plabel: String,
+        bus: String,
+    },
+    NoHardwarePwm {
+        vlabel: String,
+        plabel: String,
+    },
+}
+
+impl std::fmt::Display for PolicyErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyErrorKind::Read(err) => write!(f, "failed to read policy file: {err}"),
+            PolicyErrorKind::Parse(message) => write!(f, "{message}"),
+            PolicyErrorKind::DuplicateVlabel(vlabel) => {
+                write!(f, "virtual label `{vlabel}` is defined more than once")
+            }
+            PolicyErrorKind::InvalidPlabel { plabel, board } => write!(
+                f,
+                "`{plabel}` is not a pin of the {board}, expected GPIOn, PINn or a pin name"
+            ),
+            PolicyErrorKind::PlabelInUse { plabel, vlabel } => {
+                write!(
+                    f,
+                    "physical label `{plabel}` is already used in the same mode by `{vlabel}`"
+                )
+            }
+            PolicyErrorKind::AnalogInputUnsupported { vlabel, plabel } => write!(
+                f,
+                "`{vlabel}` uses analog input on `{plabel}`, which has no ADC"
+            ),
+            PolicyErrorKind::UnknownPortPin { port, pin } => {
+                write!(f, "port `{port}` uses `{pin}`, which is not a gpio entry")
+            }
+            PolicyErrorKind::InvalidServoLimits(vlabel) => write!(
+                f,
+                "servo limits of `{vlabel}` need min-pulse-us to be below max-pulse-us, which can be at most {} for the {} ms servo period",
+                SERVO_PERIOD.as_micros(),
+                SERVO_PERIOD.as_millis()
+            ),
+            PolicyErrorKind::UnknownInterlockPin(pin) => {
+                write!(f, "interlock uses `{pin}`, which is not a gpio entry")
+            }
+            PolicyErrorKind::InterlockTooSmall => {
+                write!(f, "an interlock needs at least two pins")
+            }
+            PolicyErrorKind::InterlockedSafeState(vlabel) => write!(
+                f,
+                "safe state of `{vlabel}` can leave an interlocked pin switched on, use its off level or float"
+            ),
+            PolicyErrorKind::InterlockedPwm { vlabel, plabel } => write!(
+                f,
+                "`{vlabel}` uses analog output on `{plabel}`, which is interlocked and can only be a digital output"
+            ),
+            PolicyErrorKind::InvalidWatchdog => {
+                write!(f, "the watchdog needs interval-ms above 0")
+            }
+            PolicyErrorKind::InvalidRateLimit(vlabel) => write!(
+                f,
+                "rate limit of `{vlabel}` needs max-changes-per-second above 0 or min-hold-ms"
+            ),
+            PolicyErrorKind::InvalidConstraints { vlabel, reason } => {
+                write!(f, "constraints of `{vlabel}` are invalid, {reason}")
+            }
+            PolicyErrorKind::DirectionConflict {
+                plabel,
+                input,
+                output,
+            } => write!(
+                f,
+                "`{plabel}` is an input for `{input}` and an output for `{output}`"
+            ),
+            PolicyErrorKind::ReservedPin {
+                vlabel,
+                plabel,
+                bus,
+            } => write!(
+                f,
+                "`{vlabel}` uses `{plabel}`, which is reserved for {bus} and only free while {bus} is disabled"
+            ),
+            PolicyErrorKind::NoHardwarePwm { vlabel, plabel } => write!(
+                f,
+                "`{vlabel}` uses analog output on `{plabel}`, which has no hardware PWM and falls back to software PWM"
+            ),
+        }
+    }
+}
+
+impl PolicyErrorKind {
+    /// Returns true for problems that only matter on some setups of the board, they do not fail a check
+    pub fn is_warning(&self) -> bool {
+        matches!(
+            self,
+            PolicyErrorKind::ReservedPin { .. } | PolicyErrorKind::NoHardwarePwm { .. }
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct PolicyError {
+    pub kind: PolicyErrorKind,
+    /// Byte range in the policy file the error points at
+    pub span: Option<Range<usize>>,
+}
+
+impl PolicyError {
+    fn new(kind: PolicyErrorKind, span: Range<usize>) -> Self {
+        Self {
+            kind,
+            span: Some(span),
+        }
+    }
+
+    /// Returns the 1-based line and column of the start of the span in `source`
+    pub fn location(&self, source: &str) -> Option<(usize, usize)> {
+        let start = self.span.as_ref()?.start.min(source.len());
+        let before = &source[..start];
+
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |index| index + 1) + 1;
+
+        Some((line, column))
+    }
+}
+
+/// Every problem found in a policy file
+#[derive(Debug)]
+pub struct PolicyErrors {
+    pub file: String,
+    pub source: String,
+    pub errors: Vec<PolicyError>,
+}
+
+impl std::fmt::Display for PolicyErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, error) in self.errors.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+
+            match error.location(&self.source) {
+                Some((line, column)) => {
+                    write!(f, "{}:{}:{}: {}", self.file, line, column, error.kind)?
+                }
+                None => write!(f, "{}: {}", self.file, error.kind)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for PolicyErrors {}
+
+/// Reads and parses a policy file without validating it, also returns the source for locating errors
+pub fn read(path: &str) -> Result<(Policies, String), PolicyErrors> {
+    let errors = |source: String, error| PolicyErrors {
+        file: path.to_string(),
+        source,
+        errors: vec![error],
+    };
+
+    let source = std::fs::read_to_string(path).map_err(|err| {
+        let error = PolicyError {
+            kind: PolicyErrorKind::Read(err),
+            span: None,
+        };
+        errors(String::new(), error)
+    })?;
+
+    match toml::from_str(&source) {
+        Ok(policies) => Ok((policies, source)),
+        Err(err) => {
+            let error = PolicyError {
+                kind: PolicyErrorKind::Parse(err.message().to_string()),
+                span: err.span(),
+            };
+            Err(errors(source, error))
+        }
+    }
+}
+
+/// Reads, parses and validates a policy file, the physical labels are resolved on `board`
+pub fn load(path: &str, board: Board) -> Result<Policies, PolicyErrors> {
+    let (mut policies, source) = read(path)?;
+    policies.board = board;
+
+    match policies.validate() {
+        errors if errors.is_empty() => Ok(policies),
+        errors => Err(PolicyErrors {
+            file: path.to_string(),
+            source,
+            errors,
+        }),
+    }
+}
+
+impl Policies {
+    /// Checks the parsed policies for mistakes the parser can not catch
+    pub fn validate(&self) -> Vec<PolicyError> {
+        let mut errors = Vec::new();
+        let mut vlabels = HashSet::new();
+        let mut pins: HashMap<u8, Vec<&WasiGpioEntry>> = HashMap::new();
+
+        let all_vlabels = self
+            .wasi
+            .gpio
+            .iter()
+            .map(|entry| &entry.vlabel)
+            .chain(self.wasi.port.iter().map(|port| &port.vlabel));
+
+        for vlabel in all_vlabels {
+            if !vlabels.insert(vlabel.get_ref()) {
+                errors.push(PolicyError::new(
+                    PolicyErrorKind::DuplicateVlabel(vlabel.get_ref().clone()),
+                    vlabel.span(),
+                ));
+            }
+        }
+
+        for entry in self.wasi.gpio.iter() {
+            let vlabel = entry.vlabel.get_ref();
+            let plabel = entry.plabel.get_ref();
+
+            let analog_input = entry
+                .modes
+                .iter()
+                .any(|mode| matches!(mode, Mode::AnalogInput | Mode::AnalogInputOutput));
+
+            match self.board.resolve(plabel) {
+                None => errors.push(PolicyError::new(
+                    PolicyErrorKind::InvalidPlabel {
+                        plabel: plabel.clone(),
+                        board: self.board.name.clone(),
+                    },
+                    entry.plabel.span(),
+                )),
+                Some(pin) => {
+                    // Sharing a pin is how a pin switches between modes, sharing a mode is ambiguous
+                    let users = pins.entry(pin.bcm).or_default();
+                    let conflict = users
+                        .iter()
+                        .find(|user| user.modes.iter().any(|mode| entry.modes.contains(mode)));
+
+                    if let Some(user) = conflict {
+                        errors.push(PolicyError::new(
+                            PolicyErrorKind::PlabelInUse {
+                                plabel: plabel.clone(),
+                                vlabel: user.vlabel.get_ref().clone(),
+                            },
+                            entry.plabel.span(),
+                        ));
+                    }
+
+                    users.push(entry);
+
+                    if analog_input && !pin.adc {
+                        errors.push(PolicyError::new(
+                            PolicyErrorKind::AnalogInputUnsupported {
+                                vlabel: vlabel.clone(),
+                                plabel: plabel.clone(),
+                            },
+                            entry.vlabel.span(),
+                        ));
+                    }
+                }
+            }
+
+            if let Some(servo) = &entry.servo
+                && (servo.min_pulse_us >= servo.max_pulse_us
+                    || servo.max_pulse_us as u128 > SERVO_PERIOD.as_micros())
+            {
+                errors.push(PolicyError::new(
+                    PolicyErrorKind::InvalidServoLimits(vlabel.clone()),
+                    entry.vlabel.span(),
+                ));
+            }
+
+            if let Some(limit) = &entry.rate_limit
+                && (limit.max_changes_per_second == Some(0)
+                    || (limit.max_changes_per_second.is_none() && limit.min_hold_ms.is_none()))
+            {
+                errors.push(PolicyError::new(
+                    PolicyErrorKind::InvalidRateLimit(vlabel.clone()),
+                    entry.vlabel.span(),
+                ));
+            }
+
+            if let Some(reason) = entry.constraints.check() {
+                errors.push(PolicyError::new(
+                    PolicyErrorKind::InvalidConstraints {
+                        vlabel: vlabel.clone(),
+                        reason,
+                    },
+                    entry.vlabel.span(),
+                ));
+            }
+        }
+
+        for port in self.wasi.port.iter() {
+            for pin in port.pins.iter() {
+                if self.find(pin.get_ref()).is_none() {
+                    errors.push(PolicyError::new(
+                        PolicyErrorKind::UnknownPortPin {
+                            port: port.vlabel.get_ref().clone(),
+                            pin: pin.get_ref().clone(),
+                        },
+                        pin.span(),
+                    ));
+                }
+            }
+        }
+
+        for interlock in self.wasi.interlock.iter() {
+            if interlock.pins.len() < 2 {
+                errors.push(PolicyError {
+                    kind: PolicyErrorKind::InterlockTooSmall,
+                    span: interlock.pins.first().map(|pin| pin.span()),
+                });
+            }
+
+            for pin in interlock.pins.iter() {
+                if self.find(pin.get_ref()).is_none() {
+                    errors.push(PolicyError::new(
+                        PolicyErrorKind::UnknownInterlockPin(pin.get_ref().clone()),
+                        pin.span(),
+                    ));
+                }
+            }
+
+            // A safe state is applied while the other pins may still be switched on
+            let on_state = match interlock.on_level {
+                OnLevel::High => SafeState::High,
+                OnLevel::Low => SafeState::Low,
+            };
+            for pin in interlock.pins.iter() {
+                if matches!(
+                    self.get_safe_state(pin.get_ref()),
+                    Some(state) if state == on_state || state == SafeState::Hold
+                ) {
+                    errors.push(PolicyError::new(
+                        PolicyErrorKind::InterlockedSafeState(pin.get_ref().clone()),
+                        pin.span(),
+                    ));
+                }
+            }
+        }
+
+        // Interlocks apply to the physical pin, so also to the other labels of an interlocked pin
+        for entry in self.wasi.gpio.iter() {
+            let analog_output = entry
+                .modes
+                .iter()
+                .any(|mode| matches!(mode, Mode::AnalogOutput | Mode::AnalogInputOutput));
+
+            if analog_output && self.is_interlocked(entry.vlabel.get_ref()) {
+                errors.push(PolicyError::new(
+                    PolicyErrorKind::InterlockedPwm {
+                        vlabel: entry.vlabel.get_ref().clone(),
+                        plabel: entry.plabel.get_ref().clone(),
+                    },
+                    entry.plabel.span(),
+                ));
+            }
+        }
+
+        if let Some(watchdog) = &self.wasi.watchdog
+            && watchdog.interval_ms == 0
+        {
+            errors.push(PolicyError {
+                kind: PolicyErrorKind::InvalidWatchdog,
+                span: None,
+            });
+        }
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BRIDGE: &str = r#"
+        [[wasi.gpio]]
+        vlabel = "BRIDGE_HIGH"
+        modes = ["digital-output"]
+        plabel = "GPIO17"
+
+        [[wasi.gpio]]
+        vlabel = "BRIDGE_LOW"
+        modes = ["digital-output"]
+        plabel = "GPIO27"
+    "#;
+
+    fn validate(source: &str) -> Vec<PolicyErrorKind> {
+        let policies: Policies = toml::from_str(source).unwrap();
+
+        policies
+            .validate()
+            .into_iter()
+            .map(|error| error.kind)
+            .collect()
+    }
+
+    /// Returns the bridge pins with `safe_state` on the high side
+    fn with_safe_state(safe_state: &str) -> String {
+        BRIDGE.replacen(
+            r#"plabel = "GPIO17""#,
+            &format!("plabel = \"GPIO17\"\nsafe-state = \"{safe_state}\""),
+            1,
+        )
+    }
+
+    /// Validates the bridge pins in an interlock, with `safe_state` on the high side when given
+    fn validate_bridge(on_level: &str, safe_state: Option<&str>) -> Vec<PolicyErrorKind> {
+        let source = match safe_state {
+            Some(safe_state) => with_safe_state(safe_state),
+            None => BRIDGE.to_string(),
+        };
+
+        validate(&format!(
+            r#"{source}
+            [[wasi.interlock]]
+            pins = ["BRIDGE_HIGH", "BRIDGE_LOW"]
+            on-level = "{on_level}"
+            "#
+        ))
+    }
+
+    #[test]
+    fn accepts_an_interlock_of_two_pins() {
+        assert!(validate_bridge("high", None).is_empty());
+    }
+
+    #[test]
+    fn refuses_an_interlock_of_one_pin() {
+        let errors = validate(&format!(
+            r#"{BRIDGE}
+            [[wasi.interlock]]
+            pins = ["BRIDGE_HIGH"]
+            "#
+        ));
+
+        assert!(matches!(errors[..], [PolicyErrorKind::InterlockTooSmall]));
+    }
+
+    #[test]
+    fn refuses_an_interlock_with_an_unknown_pin() {
+        let errors = validate(&format!(
+            r#"{BRIDGE}
+            [[wasi.interlock]]
+            pins = ["BRIDGE_HIGH", "BRIDGE_MID"]
+            "#
+        ));
+
+        assert!(matches!(
+            &errors[..],
+            [PolicyErrorKind::UnknownInterlockPin(pin)] if pin == "BRIDGE_MID"
+        ));
+    }
+
+    #[test]
+    fn accepts_interlocked_safe_states_that_switch_off() {
+        assert!(validate_bridge("high", Some("low")).is_empty());
+        assert!(validate_bridge("low", Some("high")).is_empty());
+        assert!(validate_bridge("high", Some("float")).is_empty());
+    }
+
+    #[test]
+    fn refuses_interlocked_safe_states_that_can_stay_on() {
+        for (on_level, safe_state) in [("high", "high"), ("low", "low"), ("high", "hold")] {
+            let errors = validate_bridge(on_level, Some(safe_state));
+
+            assert!(
+                matches!(
+                    &errors[..],
+                    [PolicyErrorKind::InterlockedSafeState(vlabel)] if vlabel == "BRIDGE_HIGH"
+                ),
+                "{safe_state} with on-level {on_level}: {errors:?}"
+            );
+        }
+    }
+
+    /// Validates the bridge pins in an interlock next to `HIGH_ALIAS` on the physical pin of the high side
+    fn validate_alias(modes: &str) -> Vec<PolicyErrorKind> {
+        validate(&format!(
+            r#"{BRIDGE}
+            [[wasi.gpio]]
+            vlabel = "HIGH_ALIAS"
+            modes = [{modes}]
+            plabel = "PIN11"
+
+            [[wasi.interlock]]
+            pins = ["BRIDGE_HIGH", "BRIDGE_LOW"]
+            "#
+        ))
+    }
+
+    #[test]
+    fn refuses_analog_outputs_on_an_interlocked_physical_pin() {
+        for modes in [r#""analog-output""#, r#""digital-input", "analog-output""#] {
+            let errors = validate_alias(modes);
+
+            assert!(
+                errors.iter().any(|error| matches!(
+                    error,
+                    PolicyErrorKind::InterlockedPwm { vlabel, plabel }
+                        if vlabel == "HIGH_ALIAS" && plabel == "PIN11"
+                )),
+                "{modes}: {errors:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_digital_inputs_on_an_interlocked_physical_pin() {
+        assert!(validate_alias(r#""digital-input""#).is_empty());
+    }
+
+    #[test]
+    fn accepts_any_safe_state_without_an_interlock() {
+        for safe_state in ["low", "high", "float", "hold"] {
+            assert!(
+                validate(&with_safe_state(safe_state)).is_empty(),
+                "{safe_state}"
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_an_unknown_safe_state() {
+        assert!(toml::from_str::<Policies>(&with_safe_state("off")).is_err());
+    }
+
+    #[test]
+    fn refuses_servo_pulses_longer_than_the_servo_period() {
+        let servo = |max_pulse_us| {
+            validate(&format!(
+                r#"
+                [[wasi.gpio]]
+                vlabel = "SERVO"
+                modes = ["analog-output"]
+                plabel = "GPIO18"
+
+                [wasi.gpio.servo]
+                min-pulse-us = 500
+                max-pulse-us = {max_pulse_us}
+                "#
+            ))
+        };
+
+        assert!(servo(20_000).is_empty());
+        assert!(matches!(
+            &servo(20_001)[..],
+            [PolicyErrorKind::InvalidServoLimits(vlabel)] if vlabel == "SERVO"
+        ));
+        assert!(matches!(
+            &servo(500)[..],
+            [PolicyErrorKind::InvalidServoLimits(_)]
+        ));
+    }
+
+    /// Returns the errors of a policy with the 1-based line and column they point at
+    fn locate(source: &str) -> Vec<(PolicyErrorKind, Option<(usize, usize)>)> {
+        let policies: Policies = toml::from_str(source).unwrap();
+
+        policies
+            .validate()
+            .into_iter()
+            .map(|error| {
+                let location = error.location(source);
+                (error.kind, location)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn points_a_duplicate_vlabel_at_its_second_definition() {
+        let errors = locate(
+            r#"[[wasi.gpio]]
+vlabel = "LED"
+modes = ["digital-output"]
+plabel = "GPIO17"
+
+[[wasi.gpio]]
+vlabel = "LED"
+modes = ["digital-input"]
+plabel = "GPIO27"
+"#,
+        );
+
+        assert!(matches!(
+            &errors[..],
+            [(PolicyErrorKind::DuplicateVlabel(vlabel), Some((7, 10)))] if vlabel == "LED"
+        ));
+    }
+
+    #[test]
+    fn points_an_unknown_plabel_at_the_plabel() {
+        let errors = locate(
+            r#"[[wasi.gpio]]
+vlabel = "LED"
+modes = ["digital-output"]
+plabel = "GPIO99"
+"#,
+        );
+
+        assert!(matches!(
+            &errors[..],
+            [(PolicyErrorKind::InvalidPlabel { plabel, .. }, Some((4, 10)))] if plabel == "GPIO99"
+        ));
+    }
+
+    #[test]
+    fn refuses_a_pin_used_twice_in_the_same_mode() {
+        let errors = locate(
+            r#"[[wasi.gpio]]
+vlabel = "LED"
+modes = ["digital-output"]
+plabel = "GPIO17"
+
+[[wasi.gpio]]
+vlabel = "BUZZER"
+modes = ["digital-output", "analog-output"]
+plabel = "PIN11"
+"#,
+        );
+
+        assert!(matches!(
+            &errors[..],
+            [(PolicyErrorKind::PlabelInUse { plabel, vlabel }, Some((9, 10)))]
+                if plabel == "PIN11" && vlabel == "LED"
+        ));
+    }
+
+    #[test]
+    fn accepts_a_pin_shared_in_different_modes() {
+        assert!(
+            validate(
+                r#"
+                [[wasi.gpio]]
+                vlabel = "OUT"
+                modes = ["digital-output"]
+                plabel = "GPIO17"
+
+                [[wasi.gpio]]
+                vlabel = "IN"
+                modes = ["digital-input"]
+                plabel = "GPIO17"
+                "#
+            )
+            .iter()
+            .all(PolicyErrorKind::is_warning)
+        );
+    }
+
+    #[test]
+    fn reports_parse_errors_with_their_location() {
+        let path =
+            std::env::temp_dir().join(format!("wasi-gpio-policy-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[[wasi.gpio]]\nvlabel = \"LED\"\nmodes = [\"digital-outptu\"]\nplabel = \"GPIO17\"\n",
+        )
+        .unwrap();
+
+        let path = path.to_str().unwrap();
+        let result = read(path);
+        std::fs::remove_file(path).unwrap();
+
+        let Err(errors) = result else {
+            panic!("the policy parsed");
+        };
+        assert!(matches!(
+            &errors.errors[..],
+            [error] if matches!(error.kind, PolicyErrorKind::Parse(_))
+                && error.location(&errors.source) == Some((3, 10))
+        ));
+        assert!(errors.to_string().starts_with(&format!("{path}:3:10: ")));
+    }
+
+    #[test]
+    fn reports_a_missing_file_without_a_location() {
+        let Err(errors) = read("/nonexistent/policies.toml") else {
+            panic!("the policy was read");
+        };
+
+        assert!(matches!(
+            &errors.errors[..],
+            [PolicyError {
+                kind: PolicyErrorKind::Read(_),
+                span: None
+            }]
+        ));
+        assert!(
+            errors
+                .to_string()
+                .starts_with("/nonexistent/policies.toml: ")
+        );
+    }
+}
@@ -1,44 +1,119 @@
 use crate::ctx::WasiGpioView;
 use crate::impls::GpioImpl;
-use crate::util::Shared;
+use crate::util::{CANCEL_CHECK_INTERVAL, CancelToken, Cancelled, Trigger};
 use crate::wasi::gpio::poll;
 use wasmtime::component::Resource;
+use wasmtime_wasi::p2::bindings::io::poll as io;
 
+/// The `pollable` resource handed to guests, shared by `wasi:gpio/poll` and `wasi:io/poll`
+pub type PollableResource = wasmtime_wasi::p2::DynPollable;
+
+#[derive(Clone)]
+pub enum PollableSource {
+    /// Ready once the trigger is set by a watcher
+    Trigger(Trigger),
+    /// Ready once the deadline has passed
+    Deadline(std::time::Instant),
+}
+
+#[derive(Clone)]
 pub struct Pollable {
-    pub trigger: Shared<bool>,
+    pub source: PollableSource,
 }
 
 impl Pollable {
-    pub fn new(trigger: Shared<bool>) -> Self {
-        Pollable { trigger }
+    pub fn new(trigger: Trigger) -> Self {
+        Pollable {
+            source: PollableSource::Trigger(trigger),
+        }
+    }
+
+    pub fn with_deadline(deadline: std::time::Instant) -> Self {
+        Pollable {
+            source: PollableSource::Deadline(deadline),
+        }
+    }
+}
+
+/// Runs a future until it completes or the cancel token is set
+pub async fn cancellable<F: std::future::Future>(
+    future: F,
+    cancel: &CancelToken,
+) -> Result<F::Output, Cancelled> {
+    let mut future = std::pin::pin!(future);
+
+    loop {
+        cancel.check()?;
+
+        if let Ok(output) = tokio::time::timeout(CANCEL_CHECK_INTERVAL, future.as_mut()).await {
+            return Ok(output);
+        }
+    }
+}
+
+impl<T: WasiGpioView> GpioImpl<'_, T> {
+    /// Adds a pollable to the table as a `wasi:io/poll.pollable`, the legacy `wasi:gpio/poll.pollable` shares its representation
+    pub fn push_pollable(
+        &mut self,
+        pollable: Pollable,
+    ) -> wasmtime::Result<Resource<PollableResource>> {
+        let resource = self.table().push(pollable)?;
+        wasmtime_wasi::p2::subscribe(self.table(), resource)
     }
+}
+
+#[async_trait::async_trait]
+impl wasmtime_wasi::p2::Pollable for Pollable {
+    async fn ready(&mut self) {
+        match &self.source {
+            PollableSource::Trigger(trigger) => {
+                let mut notified = std::pin::pin!(trigger.notified());
+                notified.as_mut().enable();
 
-    pub fn ready(&self) -> bool {
-        *self.trigger.lock().unwrap()
+                if !trigger.is_set() {
+                    notified.await;
+                }
+            }
+            PollableSource::Deadline(deadline) => {
+                tokio::time::sleep_until((*deadline).into()).await
+            }
+        }
     }
 }
 
 impl<'a, T: WasiGpioView> poll::Host for GpioImpl<'a, T> {
-    fn poll(&mut self, _in_: Vec<Resource<Pollable>>) -> Vec<u32> {
-        todo!("poll implementation")
+    fn poll(&mut self, in_: Vec<Resource<PollableResource>>) -> wasmtime::Result<Vec<u32>> {
+        // wasi:io traps on an empty list, the legacy interface returns no indexes
+        if in_.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let cancel = self.ctx().cancel.clone();
+        let poll = io::Host::poll(self.table(), in_);
+
+        wasmtime_wasi::runtime::in_tokio(cancellable(poll, &cancel))?
     }
 }
 
 impl<'a, T: WasiGpioView> poll::HostPollable for GpioImpl<'a, T> {
-    fn ready(&mut self, self_: Resource<Pollable>) -> bool {
-        let poll = self.table().get(&self_).unwrap();
-        poll.ready()
+    fn ready(&mut self, self_: Resource<PollableResource>) -> bool {
+        // Polled once so this also works from within the async bindings
+        wasmtime_wasi::runtime::with_ambient_tokio_runtime(|| {
+            let ready = std::pin::pin!(io::HostPollable::ready(self.table(), self_));
+            wasmtime_wasi::runtime::poll_noop(ready)
+        })
+        .and_then(Result::ok)
+        .unwrap_or(false)
     }
 
-    fn block(&mut self, self_: Resource<Pollable>) -> () {
-        let poll = self.table().get(&self_).unwrap();
-        while !poll.ready() {
-            std::thread::yield_now();
-        }
+    fn block(&mut self, self_: Resource<PollableResource>) -> wasmtime::Result<()> {
+        let cancel = self.ctx().cancel.clone();
+        let block = io::HostPollable::block(self.table(), self_);
+
+        wasmtime_wasi::runtime::in_tokio(cancellable(block, &cancel))?
     }
 
-    fn drop(&mut self, rep: Resource<Pollable>) -> wasmtime::Result<()> {
-        self.table().delete(rep).expect("failed to delete resource");
-        Ok(())
+    fn drop(&mut self, rep: Resource<PollableResource>) -> wasmtime::Result<()> {
+        io::HostPollable::drop(self.table(), rep)
     }
 }
@@ -0,0 +1,228 @@
+use super::DigitalPort;
+use super::line_request::{self, LineRequest};
+use crate::wasi::gpio::{digital, general};
+
+/// The way the pins of a port are accessed
+pub enum PortLines {
+    /// A single kernel line request, changes all pins at the same moment
+    ///
+    /// The kernel does not know about the pins other resources drive through rppal, so the port holds them in rppal as well
+    Request(LineRequest, Vec<rppal::gpio::Pin>),
+    /// Separate pins, used only when the system has no kernel interface for line requests
+    Pins(Vec<rppal::gpio::IoPin>),
+}
+
+/// Returns the mask of the bits of a port `width` pins wide
+pub(super) fn width_mask(width: u32) -> u64 {
+    u64::MAX.checked_shr(64 - width).unwrap_or(0)
+}
+
+/// Returns the level of the physical pin for bit `bit` of `value`
+fn bit_level(value: u64, bit: usize, active_level: general::ActiveLevel) -> rppal::gpio::Level {
+    let pin_state = match value & (1 << bit) {
+        0 => digital::PinState::Inactive,
+        _ => digital::PinState::Active,
+    };
+
+    crate::digital::implementations::level_for(active_level, pin_state)
+}
+
+/// Returns the value of a port whose pins read `levels`, the first one in bit 0
+fn levels_value(
+    levels: impl Iterator<Item = rppal::gpio::Level>,
+    active_level: general::ActiveLevel,
+) -> u64 {
+    levels
+        .enumerate()
+        .filter(|(_, level)| {
+            crate::digital::implementations::state_for(active_level, *level)
+                == digital::PinState::Active
+        })
+        .fold(0, |value, (bit, _)| value | (1 << bit))
+}
+
+impl DigitalPort {
+    /// Takes the pins of the port, outputs start inactive on either path
+    pub fn new(pins: &[u8], config: digital::DigitalConfig) -> Result<Self, general::GpioError> {
+        let reserved = Self::reserve_pins(pins)?;
+
+        let lines = match LineRequest::new(pins, &config) {
+            Ok(request) => PortLines::Request(request, reserved),
+            Err(err) if line_request::is_unsupported(&err) => {
+                PortLines::Pins(Self::get_pins(reserved, &config))
+            }
+            Err(err) if err.raw_os_error() == Some(libc::EBUSY) => {
+                return Err(general::GpioError::AlreadyInUse);
+            }
+            Err(err) => return Err(general::GpioError::Other(err.to_string())),
+        };
+
+        Ok(Self {
+            lines,
+            config,
+            width: pins.len() as u32,
+        })
+    }
+
+    /// Takes the pins in rppal, fails with `already-in-use` when another resource holds one of them
+    fn reserve_pins(pins: &[u8]) -> Result<Vec<rppal::gpio::Pin>, general::GpioError> {
+        let gpio =
+            rppal::gpio::Gpio::new().map_err(|e| general::GpioError::Other(e.to_string()))?;
+
+        pins.iter()
+            .map(|pin_num| match gpio.get(*pin_num) {
+                Ok(pin) => Ok(pin),
+                Err(rppal::gpio::Error::PinUsed(_)) => Err(general::GpioError::AlreadyInUse),
+                Err(e) => Err(general::GpioError::Other(e.to_string())),
+            })
+            .collect()
+    }
+
+    fn get_pins(
+        pins: Vec<rppal::gpio::Pin>,
+        config: &digital::DigitalConfig,
+    ) -> Vec<rppal::gpio::IoPin> {
+        let inactive = match config.active_level {
+            general::ActiveLevel::ActiveHigh => rppal::gpio::Level::Low,
+            general::ActiveLevel::ActiveLow => rppal::gpio::Level::High,
+        };
+
+        pins.into_iter()
+            .map(|pin| {
+                let mut pin = pin.into_io(config.pin_mode.into());
+
+                match &config.pull_resistor {
+                    Some(general::PullResistor::PullUp) => pin.set_bias(rppal::gpio::Bias::PullUp),
+                    Some(general::PullResistor::PullDown) => {
+                        pin.set_bias(rppal::gpio::Bias::PullDown)
+                    }
+                    None => {}
+                }
+
+                // Same as the line request, which starts its outputs inactive
+                if config.pin_mode == general::PinMode::Out {
+                    pin.write(inactive);
+                }
+
+                pin
+            })
+            .collect()
+    }
+
+    pub fn get_config(&self) -> &digital::DigitalConfig {
+        &self.config
+    }
+
+    /// Leaves the pins as they are when dropped instead of resetting them, a released line request keeps its levels already
+    pub fn keep_state(&mut self) {
+        if let PortLines::Pins(pins) = &mut self.lines {
+            for pin in pins.iter_mut() {
+                pin.set_reset_on_drop(false);
+            }
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        width_mask(self.width)
+    }
+
+    pub fn write(&mut self, value: u64, mask: u64) -> Result<(), general::GpioError> {
+        if self.config.pin_mode != general::PinMode::Out {
+            return Err(general::GpioError::PinModeNotAllowed);
+        }
+
+        let mask = mask & self.mask();
+
+        match &mut self.lines {
+            PortLines::Request(request, _) => request
+                .set_values(value, mask)
+                .map_err(|err| general::GpioError::Other(err.to_string())),
+            PortLines::Pins(pins) => {
+                for (bit, pin) in pins.iter_mut().enumerate() {
+                    if mask & (1 << bit) == 0 {
+                        continue;
+                    }
+
+                    pin.write(bit_level(value, bit, self.config.active_level));
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    pub fn read(&self) -> Result<u64, general::GpioError> {
+        let mask = self.mask();
+
+        match &self.lines {
+            PortLines::Request(request, _) => request
+                .get_values(mask)
+                .map_err(|err| general::GpioError::Other(err.to_string())),
+            PortLines::Pins(pins) => {
+                let levels = pins.iter().map(|pin| pin.read());
+
+                Ok(levels_value(levels, self.config.active_level))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rppal::gpio::Level;
+
+    #[test]
+    fn masks_the_width_of_the_port() {
+        assert_eq!(width_mask(0), 0);
+        assert_eq!(width_mask(1), 0b1);
+        assert_eq!(width_mask(8), 0xFF);
+        assert_eq!(width_mask(64), u64::MAX);
+    }
+
+    #[test]
+    fn writes_active_bits_at_the_active_level() {
+        let value = 0b0110;
+        let levels = |active_level| -> Vec<Level> {
+            (0..4)
+                .map(|bit| bit_level(value, bit, active_level))
+                .collect()
+        };
+
+        assert_eq!(
+            levels(general::ActiveLevel::ActiveHigh),
+            [Level::Low, Level::High, Level::High, Level::Low]
+        );
+        assert_eq!(
+            levels(general::ActiveLevel::ActiveLow),
+            [Level::High, Level::Low, Level::Low, Level::High]
+        );
+    }
+
+    #[test]
+    fn reads_pins_at_the_active_level_as_set_bits() {
+        let levels = [Level::High, Level::Low, Level::Low, Level::High];
+
+        assert_eq!(
+            levels_value(levels.into_iter(), general::ActiveLevel::ActiveHigh),
+            0b1001
+        );
+        assert_eq!(
+            levels_value(levels.into_iter(), general::ActiveLevel::ActiveLow),
+            0b0110
+        );
+    }
+
+    #[test]
+    fn reads_back_what_was_written() {
+        for active_level in [
+            general::ActiveLevel::ActiveHigh,
+            general::ActiveLevel::ActiveLow,
+        ] {
+            let value = 0b1011_0010;
+            let levels = (0..8).map(|bit| bit_level(value, bit, active_level));
+
+            assert_eq!(levels_value(levels, active_level), value);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! Minimal binding of the Linux GPIO character device (uAPI v2) multi-line requests.
+//! A single request allows every line in it to be read or written with one ioctl.
+
+use crate::wasi::gpio::{digital, general};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd};
+
+const PATH_GPIOCHIP: &str = "/dev/gpiochip";
+const CONSUMER_LABEL: &[u8] = b"wasi-gpio";
+// Labels of the gpiochips that expose the header pins as line offsets
+const DRIVER_NAMES: [&[u8]; 3] = [b"pinctrl-bcm2835", b"pinctrl-bcm2711", b"pinctrl-rp1"];
+
+const LINES_MAX: usize = 64;
+const NAME_BUFSIZE: usize = 32;
+const LINE_NUM_ATTRS_MAX: usize = 10;
+
+const LINE_FLAG_ACTIVE_LOW: u64 = 1 << 1;
+const LINE_FLAG_INPUT: u64 = 1 << 2;
+const LINE_FLAG_OUTPUT: u64 = 1 << 3;
+const LINE_FLAG_BIAS_PULL_UP: u64 = 1 << 8;
+const LINE_FLAG_BIAS_PULL_DOWN: u64 = 1 << 9;
+
+const LINE_ATTR_ID_OUTPUT_VALUES: u32 = 2;
+
+#[repr(C)]
+struct ChipInfo {
+    name: [u8; NAME_BUFSIZE],
+    label: [u8; NAME_BUFSIZE],
+    lines: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct LineConfigAttribute {
+    id: u32,
+    padding: u32,
+    value: u64,
+    mask: u64,
+}
+
+#[repr(C)]
+struct LineConfig {
+    flags: u64,
+    num_attrs: u32,
+    padding: [u32; 5],
+    attrs: [LineConfigAttribute; LINE_NUM_ATTRS_MAX],
+}
+
+#[repr(C)]
+struct LineRequestArgs {
+    offsets: [u32; LINES_MAX],
+    consumer: [u8; NAME_BUFSIZE],
+    config: LineConfig,
+    num_lines: u32,
+    event_buffer_size: u32,
+    padding: [u32; 5],
+    fd: i32,
+}
+
+#[repr(C)]
+struct LineValues {
+    bits: u64,
+    mask: u64,
+}
+
+const _: () = assert!(std::mem::size_of::<LineRequestArgs>() == 592);
+const _: () = assert!(std::mem::size_of::<LineValues>() == 16);
+
+const fn iowr<T>(nr: u64) -> u64 {
+    (3 << 30) | ((std::mem::size_of::<T>() as u64) << 16) | (0xB4 << 8) | nr
+}
+
+const GPIO_GET_CHIPINFO_IOCTL: u64 =
+    (2 << 30) | ((std::mem::size_of::<ChipInfo>() as u64) << 16) | (0xB4 << 8) | 0x01;
+const GPIO_V2_GET_LINE_IOCTL: u64 = iowr::<LineRequestArgs>(0x07);
+const GPIO_V2_LINE_GET_VALUES_IOCTL: u64 = iowr::<LineValues>(0x0E);
+const GPIO_V2_LINE_SET_VALUES_IOCTL: u64 = iowr::<LineValues>(0x0F);
+
+fn ioctl<T>(file: &File, request: u64, arg: &mut T) -> io::Result<()> {
+    // SAFETY: every request number is paired with the `repr(C)` struct the kernel expects for it
+    match unsafe { libc::ioctl(file.as_raw_fd(), request as _, arg as *mut T) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+fn find_gpiochip() -> io::Result<File> {
+    for index in 0..=u8::MAX {
+        let chip = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(format!("{}{}", PATH_GPIOCHIP, index))
+        {
+            Ok(chip) => chip,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => break,
+            Err(_) => continue,
+        };
+
+        let mut info = ChipInfo {
+            name: [0; NAME_BUFSIZE],
+            label: [0; NAME_BUFSIZE],
+            lines: 0,
+        };
+        ioctl(&chip, GPIO_GET_CHIPINFO_IOCTL, &mut info)?;
+
+        if DRIVER_NAMES.iter().any(|name| info.label.starts_with(name)) {
+            return Ok(chip);
+        }
+    }
+
+    Err(io::Error::from(io::ErrorKind::NotFound))
+}
+
+/// Returns true when `err` means the system has no GPIO character device with the v2 interface
+pub fn is_unsupported(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::NotFound || err.raw_os_error() == Some(libc::ENOTTY)
+}
+
+/// Builds the request for `pins`, which are at most `LINES_MAX`
+fn request_args(pins: &[u8], config: &digital::DigitalConfig) -> LineRequestArgs {
+    let mut flags = match config.pin_mode {
+        general::PinMode::In => LINE_FLAG_INPUT,
+        general::PinMode::Out => LINE_FLAG_OUTPUT,
+    };
+
+    if config.active_level == general::ActiveLevel::ActiveLow {
+        flags |= LINE_FLAG_ACTIVE_LOW;
+    }
+
+    match config.pull_resistor {
+        Some(general::PullResistor::PullUp) => flags |= LINE_FLAG_BIAS_PULL_UP,
+        Some(general::PullResistor::PullDown) => flags |= LINE_FLAG_BIAS_PULL_DOWN,
+        None => {}
+    }
+
+    let mut attrs = [LineConfigAttribute::default(); LINE_NUM_ATTRS_MAX];
+    let mut num_attrs = 0;
+
+    // Outputs start inactive, the kernel applies the active-low flag to the values
+    if config.pin_mode == general::PinMode::Out {
+        attrs[0] = LineConfigAttribute {
+            id: LINE_ATTR_ID_OUTPUT_VALUES,
+            padding: 0,
+            value: 0,
+            mask: super::implementations::width_mask(pins.len() as u32),
+        };
+        num_attrs = 1;
+    }
+
+    let mut args = LineRequestArgs {
+        offsets: [0; LINES_MAX],
+        consumer: [0; NAME_BUFSIZE],
+        config: LineConfig {
+            flags,
+            num_attrs,
+            padding: [0; 5],
+            attrs,
+        },
+        num_lines: pins.len() as u32,
+        event_buffer_size: 0,
+        padding: [0; 5],
+        fd: -1,
+    };
+
+    for (offset, pin) in args.offsets.iter_mut().zip(pins) {
+        *offset = *pin as u32;
+    }
+    args.consumer[..CONSUMER_LABEL.len()].copy_from_slice(CONSUMER_LABEL);
+
+    args
+}
+
+/// A set of lines requested together, bit n of every value corresponds to the n-th requested line
+pub struct LineRequest {
+    file: File,
+}
+
+impl LineRequest {
+    pub fn new(pins: &[u8], config: &digital::DigitalConfig) -> io::Result<Self> {
+        if pins.len() > LINES_MAX {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        let chip = find_gpiochip()?;
+        let mut args = request_args(pins, config);
+
+        ioctl(&chip, GPIO_V2_GET_LINE_IOCTL, &mut args)?;
+
+        // SAFETY: the kernel returned a newly opened file descriptor that is owned by nobody else
+        let file = unsafe { File::from_raw_fd(args.fd) };
+
+        Ok(Self { file })
+    }
+
+    pub fn set_values(&self, bits: u64, mask: u64) -> io::Result<()> {
+        let mut values = LineValues { bits, mask };
+        ioctl(&self.file, GPIO_V2_LINE_SET_VALUES_IOCTL, &mut values)
+    }
+
+    pub fn get_values(&self, mask: u64) -> io::Result<u64> {
+        let mut values = LineValues { bits: 0, mask };
+        ioctl(&self.file, GPIO_V2_LINE_GET_VALUES_IOCTL, &mut values)?;
+
+        Ok(values.bits & mask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::{offset_of, size_of};
+
+    fn config(
+        pin_mode: general::PinMode,
+        active_level: general::ActiveLevel,
+        pull_resistor: Option<general::PullResistor>,
+    ) -> digital::DigitalConfig {
+        digital::DigitalConfig {
+            label: "PORT".to_string(),
+            pin_mode,
+            active_level,
+            pull_resistor,
+        }
+    }
+
+    #[test]
+    fn structs_match_the_kernel_layout() {
+        // struct gpio_v2_line_request, gpio_v2_line_config and gpio_v2_line_config_attribute from linux/gpio.h
+        assert_eq!(size_of::<LineConfigAttribute>(), 24);
+        assert_eq!(size_of::<LineConfig>(), 272);
+        assert_eq!(size_of::<LineRequestArgs>(), 592);
+        assert_eq!(offset_of!(LineRequestArgs, consumer), 256);
+        assert_eq!(offset_of!(LineRequestArgs, config), 288);
+        assert_eq!(offset_of!(LineRequestArgs, num_lines), 560);
+        assert_eq!(offset_of!(LineRequestArgs, fd), 588);
+        assert_eq!(offset_of!(LineConfig, attrs), 32);
+
+        // struct gpio_v2_line_values and gpiochip_info
+        assert_eq!(size_of::<LineValues>(), 16);
+        assert_eq!(size_of::<ChipInfo>(), 68);
+    }
+
+    #[test]
+    fn ioctl_numbers_match_the_kernel() {
+        assert_eq!(GPIO_GET_CHIPINFO_IOCTL, 0x8044_B401);
+        assert_eq!(GPIO_V2_GET_LINE_IOCTL, 0xC250_B407);
+        assert_eq!(GPIO_V2_LINE_GET_VALUES_IOCTL, 0xC010_B40E);
+        assert_eq!(GPIO_V2_LINE_SET_VALUES_IOCTL, 0xC010_B40F);
+    }
+
+    #[test]
+    fn outputs_start_inactive_on_every_requested_line() {
+        let args = request_args(
+            &[17, 27, 22],
+            &config(general::PinMode::Out, general::ActiveLevel::ActiveLow, None),
+        );
+
+        assert_eq!(args.num_lines, 3);
+        assert_eq!(args.offsets[..4], [17, 27, 22, 0]);
+        assert_eq!(&args.consumer[..CONSUMER_LABEL.len()], CONSUMER_LABEL);
+        assert_eq!(args.config.flags, LINE_FLAG_OUTPUT | LINE_FLAG_ACTIVE_LOW);
+        assert_eq!(args.config.num_attrs, 1);
+
+        let attr = args.config.attrs[0];
+        assert_eq!(attr.id, LINE_ATTR_ID_OUTPUT_VALUES);
+        assert_eq!(attr.value, 0);
+        assert_eq!(attr.mask, 0b111);
+    }
+
+    #[test]
+    fn output_values_cover_a_full_request() {
+        let pins: Vec<u8> = (0..LINES_MAX as u8).collect();
+        let args = request_args(
+            &pins,
+            &config(
+                general::PinMode::Out,
+                general::ActiveLevel::ActiveHigh,
+                None,
+            ),
+        );
+
+        assert_eq!(args.config.attrs[0].mask, u64::MAX);
+    }
+
+    #[test]
+    fn inputs_have_no_output_values() {
+        let args = request_args(
+            &[5, 6],
+            &config(
+                general::PinMode::In,
+                general::ActiveLevel::ActiveHigh,
+                Some(general::PullResistor::PullUp),
+            ),
+        );
+
+        assert_eq!(args.config.flags, LINE_FLAG_INPUT | LINE_FLAG_BIAS_PULL_UP);
+        assert_eq!(args.config.num_attrs, 0);
+    }
+}
@@ -0,0 +1,143 @@
+use crate::ctx::WasiGpioView;
+use crate::digital::DigitalConfigBuilder;
+use crate::digital::implementations as digital_implementations;
+use crate::impls::GpioImpl;
+use crate::policies;
+use crate::wasi::gpio::{digital, general, port};
+use wasmtime::component::Resource;
+
+pub mod implementations;
+mod line_request;
+
+pub struct DigitalPort {
+    pub lines: implementations::PortLines,
+    pub config: digital::DigitalConfig,
+    pub width: u32,
+}
+
+fn get_pin_numbers(
+    ctx: &crate::ctx::WasiGpioCtx,
+    label: &str,
+) -> Result<Vec<u8>, general::GpioError> {
+    let pins = ctx
+        .policies
+        .get_port_pins(label)
+        .ok_or_else(|| general::GpioError::Other("Port not found in policy".to_string()))?;
+
+    if pins.is_empty() || pins.len() > 64 {
+        return Err(general::GpioError::Other(
+            "Port must contain between 1 and 64 pins".to_string(),
+        ));
+    }
+
+    Ok(pins)
+}
+
+impl<'a, T: WasiGpioView> port::Host for GpioImpl<'a, T> {}
+
+impl<'a, T: WasiGpioView> port::HostDigitalPort for GpioImpl<'a, T> {
+    fn get(
+        &mut self,
+        port_label: String,
+        flags: Vec<digital::DigitalFlag>,
+    ) -> Result<Resource<DigitalPort>, general::GpioError> {
+        let mut pin_mode = None;
+
+        for flag in flags.iter() {
+            if *flag == digital::DigitalFlag::INPUT {
+                match pin_mode {
+                    Some(_) => return Err(general::GpioError::InvalidFlag),
+                    None => pin_mode = Some(digital::PinMode::In),
+                }
+            } else if *flag == digital::DigitalFlag::OUTPUT {
+                match pin_mode {
+                    Some(_) => return Err(general::GpioError::InvalidFlag),
+                    None => pin_mode = Some(digital::PinMode::Out),
+                }
+            }
+        }
+
+        let pin_mode = match pin_mode {
+            Some(pin_mode) => pin_mode,
+            None => return Err(general::GpioError::MissingFlags),
+        };
+
+        let mode = match pin_mode {
+            digital::PinMode::In => policies::Mode::DigitalInput,
+            digital::PinMode::Out => policies::Mode::DigitalOutput,
+        };
+
+        if !self.ctx().policies.is_port_mode_allowed(&port_label, mode) {
+            return Err(general::GpioError::PinModeNotAllowed);
+        }
+
+        digital_implementations::check_invalid_flags(
+            &flags,
+            vec![digital::DigitalFlag::ACTIVE, digital::DigitalFlag::INACTIVE],
+        )
+        .map_err(|_| general::GpioError::InvalidFlag)?;
+
+        if self.ctx().policies.is_port_guarded(&port_label) {
+            return Err(general::GpioError::PinModeNotAllowed);
+        }
+
+        let pins = get_pin_numbers(self.ctx(), &port_label)?;
+
+        let config = DigitalConfigBuilder::new(port_label, pin_mode)
+            .add_flags(flags)
+            .build()
+            .map_err(|_| general::GpioError::InvalidFlag)?;
+
+        let mut port = DigitalPort::new(&pins, config)?;
+
+        if self.port_keeps_state(&port.get_config().label) {
+            port.keep_state();
+        }
+
+        self.table()
+            .push(port)
+            .map_err(|err| general::GpioError::Other(err.to_string()))
+    }
+
+    fn width(&mut self, self_: Resource<DigitalPort>) -> Result<u32, general::GpioError> {
+        Ok(self
+            .table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .width)
+    }
+
+    fn write(
+        &mut self,
+        self_: Resource<DigitalPort>,
+        value: u64,
+    ) -> Result<(), general::GpioError> {
+        self.write_masked(self_, value, u64::MAX)
+    }
+
+    fn write_masked(
+        &mut self,
+        self_: Resource<DigitalPort>,
+        value: u64,
+        mask: u64,
+    ) -> Result<(), general::GpioError> {
+        self.table()
+            .get_mut(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .write(value, mask)
+    }
+
+    fn read(&mut self, self_: Resource<DigitalPort>) -> Result<u64, general::GpioError> {
+        self.table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .read()
+    }
+
+    fn drop(&mut self, rep: Resource<DigitalPort>) -> wasmtime::Result<()> {
+        let port = self.table().delete(rep).expect("failed to delete resource");
+        let label = port.get_config().label.clone();
+        std::mem::drop(port);
+        self.apply_port_safe_states(&label)
+    }
+}
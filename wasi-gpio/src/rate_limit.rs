@@ -0,0 +1,322 @@
+use crate::ctx::WasiGpioView;
+use crate::delay;
+use crate::impls::GpioImpl;
+use crate::policies::{OnRateExceeded, RateLimit};
+use crate::util::CancelToken;
+use crate::wasi::gpio::general;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Window `max-changes-per-second` is counted over
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Times of the recent output changes of every rate limited pin, kept by BCM number so getting the pin again does not reset them
+#[derive(Default)]
+pub struct RateLimiters {
+    changes: HashMap<u8, VecDeque<Instant>>,
+}
+
+impl RateLimit {
+    /// Returns the earliest time the next change is allowed after `changes`
+    fn next_allowed(&self, changes: &VecDeque<Instant>) -> Option<Instant> {
+        let hold = self
+            .min_hold_ms
+            .zip(changes.back())
+            .map(|(hold_ms, last)| *last + Duration::from_millis(hold_ms));
+
+        let window = self
+            .max_changes_per_second
+            .filter(|max| changes.len() >= *max as usize)
+            .and_then(|max| changes.iter().rev().nth(max.saturating_sub(1) as usize))
+            .map(|oldest| *oldest + WINDOW);
+
+        hold.max(window)
+    }
+
+    /// Returns the limit that keeps to both `self` and `other`, for virtual labels that share a physical pin
+    pub fn strictest(self, other: RateLimit) -> RateLimit {
+        let max_changes_per_second =
+            match (self.max_changes_per_second, other.max_changes_per_second) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+
+        let on_exceeded = if self.on_exceeded == OnRateExceeded::Error {
+            self.on_exceeded
+        } else {
+            other.on_exceeded
+        };
+
+        RateLimit {
+            max_changes_per_second,
+            min_hold_ms: self.min_hold_ms.max(other.min_hold_ms),
+            on_exceeded,
+        }
+    }
+}
+
+impl RateLimiters {
+    /// Waits until a change of the output of `bcm` is allowed or fails with `rate-limited` when it exceeds `limit`
+    ///
+    /// A delayed change that gets cancelled because the host stops the component fails as well, it is never written
+    ///
+    /// The wait sleeps the calling thread, pin writes are synchronous in the async bindings as well
+    pub fn wait(
+        &mut self,
+        bcm: u8,
+        limit: &RateLimit,
+        cancel: &CancelToken,
+    ) -> Result<(), general::GpioError> {
+        let changes = self.changes.entry(bcm).or_default();
+
+        while changes
+            .front()
+            .is_some_and(|change| change.elapsed() >= WINDOW)
+            && changes.len() > 1
+        {
+            changes.pop_front();
+        }
+
+        if let Some(allowed) = limit.next_allowed(changes) {
+            match limit.on_exceeded {
+                OnRateExceeded::Error if allowed > Instant::now() => {
+                    return Err(general::GpioError::RateLimited);
+                }
+                OnRateExceeded::Error => {}
+                OnRateExceeded::Delay => {
+                    // The host stops the component, the change must not be written afterwards
+                    if delay::wait_until_cancellable(allowed, Duration::ZERO, cancel).is_err() {
+                        return Err(general::GpioError::Other(
+                            "stopped while waiting for the rate limit".to_string(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records a change of the output of `bcm`, once it was written
+    pub fn record(&mut self, bcm: u8) {
+        self.changes
+            .entry(bcm)
+            .or_default()
+            .push_back(Instant::now());
+    }
+}
+
+impl<'a, T: WasiGpioView> GpioImpl<'a, T> {
+    /// Applies the rate limit of the policy before the output of `vlabel` changes, `record_change` counts the change once it is written
+    pub(crate) fn limit_rate(&mut self, vlabel: &str) -> Result<(), general::GpioError> {
+        let ctx = self.ctx();
+
+        let limit = match ctx.policies.get_rate_limit(vlabel) {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        let bcm = match ctx.policies.get_pin(vlabel) {
+            Some(pin) => pin.bcm,
+            None => return Ok(()),
+        };
+
+        ctx.rate_limiters.wait(bcm, &limit, &ctx.cancel)
+    }
+
+    /// Counts a change of the output of `vlabel` against its rate limit, only changes that were written count
+    pub(crate) fn record_change(&mut self, vlabel: &str) {
+        let ctx = self.ctx();
+
+        if ctx.policies.get_rate_limit(vlabel).is_none() {
+            return;
+        }
+
+        if let Some(pin) = ctx.policies.get_pin(vlabel) {
+            ctx.rate_limiters.record(pin.bcm);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(
+        max_changes_per_second: Option<u32>,
+        min_hold_ms: Option<u64>,
+        on_exceeded: OnRateExceeded,
+    ) -> RateLimit {
+        RateLimit {
+            max_changes_per_second,
+            min_hold_ms,
+            on_exceeded,
+        }
+    }
+
+    #[test]
+    fn strictest_keeps_to_both_limits() {
+        let strictest = limit(Some(10), None, OnRateExceeded::Delay).strictest(limit(
+            Some(2),
+            Some(500),
+            OnRateExceeded::Error,
+        ));
+
+        assert_eq!(strictest.max_changes_per_second, Some(2));
+        assert_eq!(strictest.min_hold_ms, Some(500));
+        assert_eq!(strictest.on_exceeded, OnRateExceeded::Error);
+
+        let strictest = limit(None, Some(100), OnRateExceeded::Delay).strictest(limit(
+            Some(5),
+            Some(50),
+            OnRateExceeded::Delay,
+        ));
+
+        assert_eq!(strictest.max_changes_per_second, Some(5));
+        assert_eq!(strictest.min_hold_ms, Some(100));
+        assert_eq!(strictest.on_exceeded, OnRateExceeded::Delay);
+    }
+
+    #[test]
+    fn limits_every_label_of_a_physical_pin() {
+        let policies: crate::policies::Policies = toml::from_str(
+            r#"
+            [[wasi.gpio]]
+            vlabel = "RELAY"
+            modes = ["digital-output"]
+            plabel = "GPIO17"
+            rate-limit = { min-hold-ms = 500 }
+
+            [[wasi.gpio]]
+            vlabel = "RELAY_IO"
+            modes = ["digital-input-output"]
+            plabel = "PIN11"
+            rate-limit = { max-changes-per-second = 2, on-exceeded = "delay" }
+
+            [[wasi.gpio]]
+            vlabel = "RELAY_TONE"
+            modes = ["analog-output"]
+            plabel = "GPIO17"
+            "#,
+        )
+        .unwrap();
+
+        for vlabel in ["RELAY", "RELAY_IO", "RELAY_TONE"] {
+            let limit = policies.get_rate_limit(vlabel).unwrap();
+
+            assert_eq!(limit.max_changes_per_second, Some(2), "{vlabel}");
+            assert_eq!(limit.min_hold_ms, Some(500), "{vlabel}");
+            assert_eq!(limit.on_exceeded, OnRateExceeded::Error, "{vlabel}");
+            assert!(policies.is_output_guarded(vlabel), "{vlabel}");
+        }
+    }
+
+    #[test]
+    fn first_change_is_always_allowed() {
+        let limit = limit(Some(1), Some(500), OnRateExceeded::Error);
+
+        assert_eq!(limit.next_allowed(&VecDeque::new()), None);
+    }
+
+    #[test]
+    fn min_hold_counts_from_the_last_change() {
+        let limit = limit(None, Some(500), OnRateExceeded::Error);
+        let now = Instant::now();
+        let changes = VecDeque::from([now - Duration::from_millis(300), now]);
+
+        assert_eq!(
+            limit.next_allowed(&changes),
+            Some(now + Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn window_counts_from_the_oldest_change_within_the_limit() {
+        let limit = limit(Some(2), None, OnRateExceeded::Error);
+        let now = Instant::now();
+        let first = now - Duration::from_millis(600);
+        let second = now - Duration::from_millis(300);
+
+        // Below the limit nothing has to be waited for
+        assert_eq!(limit.next_allowed(&VecDeque::from([second])), None);
+
+        assert_eq!(
+            limit.next_allowed(&VecDeque::from([first, second])),
+            Some(first + WINDOW)
+        );
+
+        // Only the last `max` changes count
+        assert_eq!(
+            limit.next_allowed(&VecDeque::from([first, second, now])),
+            Some(second + WINDOW)
+        );
+    }
+
+    #[test]
+    fn later_of_hold_and_window_wins() {
+        let limit = limit(Some(1), Some(2000), OnRateExceeded::Error);
+        let now = Instant::now();
+
+        assert_eq!(
+            limit.next_allowed(&VecDeque::from([now])),
+            Some(now + Duration::from_millis(2000))
+        );
+    }
+
+    #[test]
+    fn error_mode_refuses_until_the_change_is_allowed() {
+        let limit = limit(Some(1), None, OnRateExceeded::Error);
+        let cancel = CancelToken::new();
+        let mut limiters = RateLimiters::default();
+
+        assert!(limiters.wait(17, &limit, &cancel).is_ok());
+        limiters.record(17);
+
+        assert!(matches!(
+            limiters.wait(17, &limit, &cancel),
+            Err(general::GpioError::RateLimited)
+        ));
+
+        // Other pins have their own history
+        assert!(limiters.wait(27, &limit, &cancel).is_ok());
+    }
+
+    #[test]
+    fn unwritten_changes_are_not_counted() {
+        let limit = limit(Some(1), None, OnRateExceeded::Error);
+        let cancel = CancelToken::new();
+        let mut limiters = RateLimiters::default();
+
+        for _ in 0..3 {
+            assert!(limiters.wait(17, &limit, &cancel).is_ok());
+        }
+    }
+
+    #[test]
+    fn delay_mode_gives_up_once_cancelled() {
+        let limit = limit(None, Some(60_000), OnRateExceeded::Delay);
+        let cancel = CancelToken::new();
+        let mut limiters = RateLimiters::default();
+
+        limiters.record(17);
+        cancel.cancel();
+
+        assert!(matches!(
+            limiters.wait(17, &limit, &cancel),
+            Err(general::GpioError::Other(_))
+        ));
+    }
+
+    #[test]
+    fn delay_mode_waits_for_the_hold() {
+        let limit = limit(None, Some(20), OnRateExceeded::Delay);
+        let cancel = CancelToken::new();
+        let mut limiters = RateLimiters::default();
+
+        limiters.record(17);
+        let start = Instant::now();
+
+        assert!(limiters.wait(17, &limit, &cancel).is_ok());
+        assert!(start.elapsed() >= Duration::from_millis(15));
+    }
+}
@@ -0,0 +1,118 @@
+use crate::ctx::WasiGpioView;
+use crate::impls::GpioImpl;
+use crate::policies::{Policies, SafeState};
+
+impl SafeState {
+    /// Puts `bcm` in the safe state, fails with `PinUsed` while a resource still holds the pin
+    pub fn apply(&self, bcm: u8) -> rppal::gpio::Result<()> {
+        if *self == SafeState::Hold {
+            return Ok(());
+        }
+
+        let pin = rppal::gpio::Gpio::new()?.get(bcm)?;
+
+        // The pin keeps the safe state after the host exits
+        match self {
+            SafeState::Low => pin.into_output_low().set_reset_on_drop(false),
+            SafeState::High => pin.into_output_high().set_reset_on_drop(false),
+            SafeState::Float => pin.into_input().set_reset_on_drop(false),
+            SafeState::Hold => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Safe states of the pins of a policy, taken before the policies are moved into a store
+#[derive(Debug, Clone, Default)]
+pub struct SafeStates(Vec<(String, u8, SafeState)>);
+
+impl SafeStates {
+    /// Puts every pin in its safe state once the resources holding it are dropped, returns the pins that failed
+    pub fn apply(&self) -> Vec<(String, rppal::gpio::Error)> {
+        self.0
+            .iter()
+            .filter_map(|(vlabel, bcm, safe_state)| {
+                safe_state
+                    .apply(*bcm)
+                    .err()
+                    .map(|err| (vlabel.clone(), err))
+            })
+            .collect()
+    }
+}
+
+impl Policies {
+    /// Returns the safe states of the pins, virtual labels that share a pin get the safe state of the first one
+    pub fn safe_states(&self) -> SafeStates {
+        let mut safe_states: Vec<(String, u8, SafeState)> = Vec::new();
+
+        for entry in self.wasi.gpio.iter() {
+            let (Some(safe_state), Some(pin)) =
+                (entry.safe_state, self.get_pin(entry.vlabel.get_ref()))
+            else {
+                continue;
+            };
+
+            if safe_states.iter().any(|(_, bcm, _)| *bcm == pin.bcm) {
+                continue;
+            }
+            safe_states.push((entry.vlabel.get_ref().clone(), pin.bcm, safe_state));
+        }
+
+        SafeStates(safe_states)
+    }
+}
+
+impl<'a, T: WasiGpioView> GpioImpl<'a, T> {
+    /// Returns true when the safe state holds the pin, its resources then leave it as it is when dropped
+    ///
+    /// Other safe states keep the reset on drop, so a pin is not left driven when the host panics or exits before applying them
+    pub(crate) fn keeps_state(&mut self, vlabel: &str) -> bool {
+        self.ctx().policies.get_safe_state(vlabel) == Some(SafeState::Hold)
+    }
+
+    /// Puts `vlabel` in its safe state after its resource was dropped
+    pub(crate) fn apply_safe_state(&mut self, vlabel: &str) -> wasmtime::Result<()> {
+        let policies = &self.ctx().policies;
+
+        if let (Some(safe_state), Some(pin)) =
+            (policies.get_safe_state(vlabel), policies.get_pin(vlabel))
+        {
+            match safe_state.apply(pin.bcm) {
+                // A waveform or stepper still drives the pin, it gets its safe state when the host exits
+                Err(rppal::gpio::Error::PinUsed(_)) => {}
+                result => result.map_err(|err| {
+                    anyhow::anyhow!("failed to put `{vlabel}` in its safe state: {err}")
+                })?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Puts the pins of a port in their safe states after the port was dropped
+    pub(crate) fn apply_port_safe_states(&mut self, vlabel: &str) -> wasmtime::Result<()> {
+        let labels: Vec<String> = match self.ctx().policies.get_port_labels(vlabel) {
+            Some(labels) => labels.into_iter().map(String::from).collect(),
+            None => return Ok(()),
+        };
+
+        for label in labels.iter() {
+            self.apply_safe_state(label)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns true when the safe state of a pin of the port holds it
+    pub(crate) fn port_keeps_state(&mut self, vlabel: &str) -> bool {
+        let policies = &self.ctx().policies;
+
+        policies.get_port_labels(vlabel).is_some_and(|labels| {
+            labels
+                .iter()
+                .any(|label| policies.get_safe_state(label) == Some(SafeState::Hold))
+        })
+    }
+}
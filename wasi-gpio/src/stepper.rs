@@ -0,0 +1,699 @@
+use crate::ctx::WasiGpioView;
+use crate::digital::DigitalOutPin;
+use crate::impls::GpioImpl;
+use crate::util::{CancelToken, Shared, SharedExt, Trigger};
+use crate::wasi::gpio::{digital, general, stepper};
+use crate::{delay, poll, util};
+use wasmtime::component::Resource;
+
+/// Width of the pulse on the STEP pin, long enough for the common A4988/DRV8825/TMC drivers
+const STEP_PULSE: std::time::Duration = std::time::Duration::from_micros(2);
+
+/// Time the thread waits before checking for a new move while the motor stands still
+const IDLE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(1);
+
+/// Range of speeds a profile may use, slower steps would be hours apart and faster ones overlap the step pulse
+const SPEED_RANGE: std::ops::RangeInclusive<f32> = 1e-3..=250_000.;
+
+/// Smallest acceleration a profile may use apart from 0
+const MIN_ACCELERATION: f32 = 1e-3;
+
+/// Refuses profiles with speeds the stepper thread can not turn into step intervals
+fn check_profile(profile: &stepper::MotionProfile) -> Result<(), general::GpioError> {
+    let acceleration = profile.acceleration;
+
+    if !SPEED_RANGE.contains(&profile.max_speed)
+        || !acceleration.is_finite()
+        || (acceleration != 0. && acceleration < MIN_ACCELERATION)
+    {
+        return Err(general::GpioError::Other(
+            "Invalid motion profile".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+struct StepperPin {
+    pin: Shared<rppal::gpio::OutputPin>,
+    active: rppal::gpio::Level,
+    inactive: rppal::gpio::Level,
+}
+
+impl StepperPin {
+    fn new(pin: &DigitalOutPin) -> Self {
+        Self {
+            pin: pin.clone_pin(),
+            active: pin.get_level(digital::PinState::Active),
+            inactive: pin.get_level(digital::PinState::Inactive),
+        }
+    }
+
+    fn write(&self, active: bool) {
+        let level = match active {
+            true => self.active,
+            false => self.inactive,
+        };
+
+        (*self.pin.lock().unwrap()).write(level);
+    }
+}
+
+pub struct Motion {
+    profile: stepper::MotionProfile,
+    position: i64,
+    target: i64,
+    /// Speed of the last step in steps per second, 0 when standing still
+    speed: f32,
+    /// Direction of the last step, 1 is the positive direction
+    direction: i64,
+    waiting: Vec<Trigger>,
+}
+
+impl Motion {
+    fn new(profile: stepper::MotionProfile) -> Self {
+        Self {
+            profile,
+            position: 0,
+            target: 0,
+            speed: 0.,
+            direction: 1,
+            waiting: Vec::new(),
+        }
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.speed > 0. || self.position != self.target
+    }
+
+    /// Returns a trigger that is set once the motor stands still at its target
+    pub fn watch_done(&mut self) -> Trigger {
+        let trigger = Trigger::new(!self.is_moving());
+
+        if self.is_moving() {
+            self.waiting.push(trigger.clone());
+        }
+
+        trigger
+    }
+
+    /// Moves the target to the closest position the motor can decelerate to
+    pub fn stop(&mut self) {
+        if self.speed == 0. {
+            self.target = self.position;
+            return;
+        }
+
+        let steps_to_stop = match self.profile.acceleration > 0. {
+            true => self.ramp_level() as i64,
+            false => 0,
+        };
+
+        self.target = self.position.saturating_add(self.direction * steps_to_stop);
+    }
+
+    /// Returns how many steps of acceleration the current speed is from standstill
+    ///
+    /// The n-th step from standstill runs at sqrt(2an), rounding keeps the ramps free of float errors
+    fn ramp_level(&self) -> f32 {
+        (self.speed * self.speed / (2. * self.profile.acceleration)).round()
+    }
+
+    /// Decides the direction of the next step and the time until the step after it
+    fn next_step(&mut self) -> Option<(i64, std::time::Duration)> {
+        // Targets and positions span all of i64, their distance does not fit in one
+        let remaining = i128::from(self.target) - i128::from(self.position);
+        let wanted = remaining.signum() as i64;
+        let max_speed = self.profile.max_speed;
+        let acceleration = self.profile.acceleration;
+
+        if max_speed <= 0. {
+            self.speed = 0.;
+            return None;
+        }
+
+        if acceleration <= 0. {
+            if wanted == 0 {
+                self.speed = 0.;
+                return None;
+            }
+
+            self.direction = wanted;
+            self.speed = max_speed;
+            return Some((
+                self.direction,
+                std::time::Duration::from_secs_f32(1. / max_speed),
+            ));
+        }
+
+        // Speed of the first step when starting from standstill
+        let min_speed = (2. * acceleration).sqrt().min(max_speed);
+
+        if self.speed == 0. {
+            if wanted == 0 {
+                return None;
+            }
+
+            self.direction = wanted;
+            self.speed = min_speed;
+        } else {
+            // Decelerating from level n takes n steps, the last one at the speed of the first
+            let level = self.ramp_level();
+            let decelerate = wanted != self.direction || remaining.unsigned_abs() as f32 <= level;
+
+            let level = match decelerate {
+                true => level - 1.,
+                false => level + 1.,
+            };
+            let speed = (2. * acceleration * level).max(0.).sqrt().min(max_speed);
+
+            if speed >= min_speed {
+                self.speed = speed;
+            } else if wanted == self.direction {
+                self.speed = min_speed;
+            } else {
+                // Stands still now, the next step can go the other way
+                self.speed = 0.;
+                return self.next_step();
+            }
+        }
+
+        Some((
+            self.direction,
+            std::time::Duration::from_secs_f32(1. / self.speed),
+        ))
+    }
+
+    fn finish(&mut self) {
+        for trigger in self.waiting.drain(..) {
+            trigger.set();
+        }
+    }
+}
+
+fn run(
+    step: StepperPin,
+    dir: StepperPin,
+    dir_setup: std::time::Duration,
+    motion: Shared<Motion>,
+    cancel: CancelToken,
+) -> Result<(), util::Cancelled> {
+    // Timing is still best effort when the host is not allowed to use realtime scheduling
+    let _ = util::set_realtime_priority();
+
+    let mut deadline = None;
+    let mut last_direction = None;
+
+    loop {
+        cancel.check()?;
+
+        let next_step = {
+            let mut motion = motion.lock().unwrap();
+            let next_step = motion.next_step();
+            if next_step.is_none() {
+                motion.finish();
+            }
+
+            next_step
+        };
+
+        let (direction, interval) = match next_step {
+            Some(next_step) => next_step,
+            None => {
+                deadline = None;
+                std::thread::sleep(IDLE_INTERVAL);
+                continue;
+            }
+        };
+
+        dir.write(direction > 0);
+
+        // Drivers read DIR on the STEP edge, so a new direction has to settle first
+        if last_direction != Some(direction) {
+            delay::wait_until(
+                std::time::Instant::now() + dir_setup,
+                delay::DEFAULT_SPIN_THRESHOLD,
+            );
+            last_direction = Some(direction);
+        }
+
+        step.write(true);
+        delay::wait_until(
+            std::time::Instant::now() + STEP_PULSE,
+            delay::DEFAULT_SPIN_THRESHOLD,
+        );
+        step.write(false);
+
+        motion.lock().unwrap().position += direction;
+
+        let next_deadline = deadline.unwrap_or_else(std::time::Instant::now) + interval;
+        delay::wait_until_cancellable(next_deadline, delay::DEFAULT_SPIN_THRESHOLD, &cancel)?;
+        deadline = Some(next_deadline);
+    }
+}
+
+pub struct Stepper {
+    pub motion: Shared<Motion>,
+    enable: Option<StepperPin>,
+    cancel: CancelToken,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Stepper {
+    fn new(
+        step: &DigitalOutPin,
+        dir: &DigitalOutPin,
+        enable: Option<&DigitalOutPin>,
+        profile: stepper::MotionProfile,
+        dir_setup: std::time::Duration,
+    ) -> Self {
+        let motion = Shared::make_shared(Motion::new(profile));
+
+        let enable = enable.map(StepperPin::new);
+        if let Some(enable) = &enable {
+            enable.write(true);
+        }
+
+        // Food for thread
+        let step = StepperPin::new(step);
+        let dir = StepperPin::new(dir);
+        let motion_clone = motion.clone();
+        let cancel = CancelToken::new();
+        let cancel_clone = cancel.clone();
+
+        let thread = std::thread::spawn(move || {
+            let _ = run(step, dir, dir_setup, motion_clone, cancel_clone);
+        });
+
+        Self {
+            motion,
+            enable,
+            cancel,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        if let Some(enable) = &self.enable {
+            enable.write(enabled);
+        }
+    }
+}
+
+// The thread also stops when the table is dropped with the store, the pins are released once the drop returns
+// Nothing is written on the way out, the digital outputs the pins came from apply their safe states, hold included
+impl Drop for Stepper {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<'a, T: WasiGpioView> GpioImpl<'a, T> {
+    fn get_motion(
+        &mut self,
+        self_: &Resource<Stepper>,
+    ) -> Result<Shared<Motion>, general::GpioError> {
+        Ok(self
+            .table()
+            .get(self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .motion
+            .clone())
+    }
+}
+
+impl<'a, T: WasiGpioView> stepper::Host for GpioImpl<'a, T> {}
+
+impl<'a, T: WasiGpioView> stepper::HostStepper for GpioImpl<'a, T> {
+    fn get(
+        &mut self,
+        step: Resource<DigitalOutPin>,
+        dir: Resource<DigitalOutPin>,
+        enable: Option<Resource<DigitalOutPin>>,
+        profile: stepper::MotionProfile,
+    ) -> Result<Resource<Stepper>, general::GpioError> {
+        check_profile(&profile)?;
+
+        let labels = [Some(&step), Some(&dir), enable.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|pin| self.table().get(pin).map(|pin| pin.config.label.clone()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| general::GpioError::ResourceInvalidated)?;
+
+        // One pin can not carry both STEP and DIR, not even under two labels
+        let policies = &self.ctx().policies;
+        for (index, label) in labels.iter().enumerate() {
+            if labels[..index]
+                .iter()
+                .any(|other| policies.is_same_pin(label, other))
+            {
+                return Err(general::GpioError::AlreadyInUse);
+            }
+        }
+
+        // Steppers toggle their pins on their own thread, past the rate limit and interlocks
+        if labels
+            .iter()
+            .any(|label| self.ctx().policies.is_output_guarded(label))
+        {
+            return Err(general::GpioError::PinModeNotAllowed);
+        }
+
+        let dir_setup = self
+            .ctx()
+            .policies
+            .get_stepper_timing(&labels[1])
+            .unwrap_or_default()
+            .dir_setup_us;
+
+        let table = self.table();
+
+        let step = table
+            .get(&step)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?;
+        let dir = table
+            .get(&dir)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?;
+        let enable = match &enable {
+            Some(enable) => Some(
+                table
+                    .get(enable)
+                    .map_err(|_| general::GpioError::ResourceInvalidated)?,
+            ),
+            None => None,
+        };
+
+        let stepper = Stepper::new(
+            step,
+            dir,
+            enable,
+            profile,
+            std::time::Duration::from_micros(dir_setup),
+        );
+
+        self.table()
+            .push(stepper)
+            .map_err(|err| general::GpioError::Other(err.to_string()))
+    }
+
+    fn set_profile(
+        &mut self,
+        self_: Resource<Stepper>,
+        profile: stepper::MotionProfile,
+    ) -> Result<(), general::GpioError> {
+        check_profile(&profile)?;
+
+        self.get_motion(&self_)?.lock().unwrap().profile = profile;
+        Ok(())
+    }
+
+    fn set_enabled(
+        &mut self,
+        self_: Resource<Stepper>,
+        enabled: bool,
+    ) -> Result<(), general::GpioError> {
+        self.table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .set_enabled(enabled);
+        Ok(())
+    }
+
+    fn position(&mut self, self_: Resource<Stepper>) -> Result<i64, general::GpioError> {
+        Ok(self.get_motion(&self_)?.lock().unwrap().position)
+    }
+
+    fn set_position(
+        &mut self,
+        self_: Resource<Stepper>,
+        position: i64,
+    ) -> Result<(), general::GpioError> {
+        let motion = self.get_motion(&self_)?;
+        let mut motion = motion.lock().unwrap();
+
+        if motion.is_moving() {
+            return Err(general::GpioError::WouldBlock);
+        }
+
+        motion.position = position;
+        motion.target = position;
+        Ok(())
+    }
+
+    fn move_to(
+        &mut self,
+        self_: Resource<Stepper>,
+        position: i64,
+    ) -> Result<(), general::GpioError> {
+        self.get_motion(&self_)?.lock().unwrap().target = position;
+        Ok(())
+    }
+
+    fn move_by(&mut self, self_: Resource<Stepper>, steps: i64) -> Result<(), general::GpioError> {
+        let motion = self.get_motion(&self_)?;
+        let mut motion = motion.lock().unwrap();
+
+        motion.target = motion.target.saturating_add(steps);
+        Ok(())
+    }
+
+    fn stop(&mut self, self_: Resource<Stepper>) -> Result<(), general::GpioError> {
+        self.get_motion(&self_)?.lock().unwrap().stop();
+        Ok(())
+    }
+
+    fn is_moving(&mut self, self_: Resource<Stepper>) -> Result<bool, general::GpioError> {
+        Ok(self.get_motion(&self_)?.lock().unwrap().is_moving())
+    }
+
+    fn watch_done(
+        &mut self,
+        self_: Resource<Stepper>,
+    ) -> Result<Resource<poll::PollableResource>, general::GpioError> {
+        let trigger = self.get_motion(&self_)?.lock().unwrap().watch_done();
+
+        self.push_pollable(poll::Pollable::new(trigger))
+            .map_err(|err| general::GpioError::Other(err.to_string()))
+    }
+
+    fn drop(&mut self, rep: Resource<Stepper>) -> wasmtime::Result<()> {
+        self.table().delete(rep).expect("failed to delete resource");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motion(max_speed: f32, acceleration: f32) -> Motion {
+        Motion::new(stepper::MotionProfile {
+            max_speed,
+            acceleration,
+        })
+    }
+
+    /// Takes up to `max` steps like the stepper thread does, returns the direction and speed of every step
+    fn steps(motion: &mut Motion, max: usize) -> Vec<(i64, f32)> {
+        let mut steps = Vec::new();
+
+        while steps.len() < max {
+            let Some((direction, interval)) = motion.next_step() else {
+                break;
+            };
+
+            motion.position += direction;
+            steps.push((direction, 1. / interval.as_secs_f32()));
+        }
+
+        steps
+    }
+
+    #[test]
+    fn stops_at_the_target_without_overshooting() {
+        for (max_speed, acceleration, target) in [
+            (100., 50., 1000),
+            (100., 50., 10),
+            (1000., 3., 1),
+            (500., 0., 200),
+            (250., 1000., -300),
+        ] {
+            let mut motion = motion(max_speed, acceleration);
+            motion.target = target;
+            let steps = steps(&mut motion, 100_000);
+
+            assert_eq!(
+                steps.len() as i64,
+                target.abs(),
+                "{max_speed} {acceleration} {target}"
+            );
+            assert!(
+                steps
+                    .iter()
+                    .all(|(direction, _)| *direction == target.signum())
+            );
+            assert_eq!(motion.position, target);
+            assert!(!motion.is_moving());
+        }
+    }
+
+    #[test]
+    fn accelerates_and_decelerates_symmetrically() {
+        let mut motion = motion(100., 50.);
+        motion.target = 1000;
+        let speeds: Vec<f32> = steps(&mut motion, 100_000)
+            .into_iter()
+            .map(|(_, speed)| speed)
+            .collect();
+
+        let ramp_up = speeds.iter().take_while(|speed| **speed < 100.).count();
+        let ramp_down = speeds
+            .iter()
+            .rev()
+            .take_while(|speed| **speed < 100.)
+            .count();
+        assert!(ramp_up > 1);
+        assert!(ramp_up.abs_diff(ramp_down) <= 1, "{ramp_up} {ramp_down}");
+
+        // Every step of the ramps is within one step of acceleration of its mirror image
+        let step_change = (2. * 50_f32).sqrt();
+        for (up, down) in speeds
+            .iter()
+            .zip(speeds.iter().rev())
+            .take(ramp_up.min(ramp_down))
+        {
+            assert!((up - down).abs() <= step_change, "{up} {down}");
+        }
+
+        for pair in speeds[..ramp_up].windows(2) {
+            assert!(pair[0] <= pair[1]);
+        }
+        for pair in speeds[speeds.len() - ramp_down..].windows(2) {
+            assert!(pair[0] >= pair[1]);
+        }
+    }
+
+    #[test]
+    fn slows_down_before_reversing() {
+        let mut motion = motion(100., 50.);
+        motion.target = 1000;
+        steps(&mut motion, 200);
+        let speed = motion.speed;
+        assert_eq!(speed, 100.);
+
+        motion.target = -1000;
+        let steps = steps(&mut motion, 100_000);
+        let reversal = steps
+            .iter()
+            .position(|(direction, _)| *direction == -1)
+            .unwrap();
+
+        // The motor keeps going forward while it slows down, and only then turns around
+        assert!(
+            steps[..reversal]
+                .iter()
+                .all(|(direction, _)| *direction == 1)
+        );
+        for pair in steps[..reversal].windows(2) {
+            assert!(pair[0].1 >= pair[1].1);
+        }
+        assert!(steps[reversal].1 <= (2. * 50_f32).sqrt() + f32::EPSILON);
+        assert!(
+            steps[reversal..]
+                .iter()
+                .all(|(direction, _)| *direction == -1)
+        );
+        assert_eq!(motion.position, -1000);
+    }
+
+    #[test]
+    fn stop_decelerates_to_the_closest_position() {
+        let mut motion = motion(100., 50.);
+        motion.target = 1000;
+        steps(&mut motion, 200);
+        let position = motion.position;
+
+        motion.stop();
+        let steps = steps(&mut motion, 100_000);
+
+        assert!(!steps.is_empty());
+        assert!(steps.iter().all(|(direction, _)| *direction == 1));
+        for pair in steps.windows(2) {
+            assert!(pair[0].1 >= pair[1].1);
+        }
+        assert_eq!(motion.position, position + steps.len() as i64);
+        assert!(motion.position < 1000);
+        assert!(!motion.is_moving());
+    }
+
+    #[test]
+    fn stop_at_standstill_keeps_the_position() {
+        let mut motion = motion(100., 50.);
+        motion.target = 1000;
+
+        motion.stop();
+
+        assert!(steps(&mut motion, 10).is_empty());
+        assert_eq!(motion.position, 0);
+    }
+
+    #[test]
+    fn moves_between_the_ends_of_the_range() {
+        for (max_speed, acceleration, position, target) in [
+            (100., 50., i64::MIN, i64::MAX),
+            (100., 50., i64::MAX, i64::MIN),
+            (500., 0., i64::MIN, i64::MAX),
+            (100., 50., i64::MAX - 3, i64::MAX),
+            (100., 50., i64::MIN + 3, i64::MIN),
+        ] {
+            let mut motion = motion(max_speed, acceleration);
+            motion.position = position;
+            motion.target = target;
+            let taken = steps(&mut motion, 100);
+
+            let direction = (target > position) as i64 * 2 - 1;
+            assert!(taken.iter().all(|(step, _)| *step == direction));
+            assert_eq!(
+                taken.len() as u64,
+                target.abs_diff(position).min(100),
+                "{position} {target}"
+            );
+
+            motion.stop();
+            steps(&mut motion, 100_000);
+            assert!(!motion.is_moving());
+        }
+    }
+
+    #[test]
+    fn tells_labels_of_one_pin_apart_from_other_pins() {
+        let policies: crate::policies::Policies = toml::from_str(
+            r#"
+            [[wasi.gpio]]
+            vlabel = "STEP"
+            modes = ["digital-output"]
+            plabel = "GPIO17"
+
+            [[wasi.gpio]]
+            vlabel = "STEP_ALIAS"
+            modes = ["digital-output"]
+            plabel = "PIN11"
+
+            [[wasi.gpio]]
+            vlabel = "DIR"
+            modes = ["digital-output"]
+            plabel = "GPIO27"
+            "#,
+        )
+        .unwrap();
+
+        assert!(policies.is_same_pin("STEP", "STEP"));
+        assert!(policies.is_same_pin("STEP", "STEP_ALIAS"));
+        assert!(!policies.is_same_pin("STEP", "DIR"));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::ctx::WasiGpioView;
+use crate::impls::GpioImpl;
+use crate::util::{Shared, SharedExt, Trigger};
+use crate::wasi::gpio::{general, tone};
+use crate::{policies, poll};
+use wasmtime::component::Resource;
+
+/// The way the square wave is generated
+pub enum ToneOutput {
+    Hardware(rppal::pwm::Pwm),
+    Software(rppal::gpio::OutputPin),
+}
+
+pub struct ToneState {
+    output: ToneOutput,
+    playing: bool,
+    /// Increases with every started tone so a timed stop does not end a newer tone
+    generation: u64,
+    waiting: Vec<Trigger>,
+}
+
+pub struct Tone {
+    pub state: Shared<ToneState>,
+    pub constraints: policies::PinConstraints,
+    /// Virtual label of the pin, put in its safe state once the tone is dropped
+    pub label: String,
+}
+
+/// Functions that route a hardware PWM channel to a GPIO on the BCM2835 to BCM2711, (channel, BCM number, function)
+///
+/// GPIO 40 and above are not on any builtin board, they are only reachable on the compute modules that bring them out through a board description file
+const BCM_PWM_ROUTES: &[(u8, u8, rppal::gpio::Mode)] = &[
+    (0, 12, rppal::gpio::Mode::Alt0),
+    (0, 18, rppal::gpio::Mode::Alt5),
+    (0, 40, rppal::gpio::Mode::Alt0),
+    (0, 52, rppal::gpio::Mode::Alt1),
+    (1, 13, rppal::gpio::Mode::Alt0),
+    (1, 19, rppal::gpio::Mode::Alt5),
+    (1, 41, rppal::gpio::Mode::Alt0),
+    (1, 45, rppal::gpio::Mode::Alt0),
+    (1, 53, rppal::gpio::Mode::Alt1),
+];
+
+/// Functions that route a hardware PWM channel to a GPIO on the RP1 of the Pi 5
+const RP1_PWM_ROUTES: &[(u8, u8, rppal::gpio::Mode)] = &[
+    (0, 12, rppal::gpio::Mode::Alt0),
+    (1, 13, rppal::gpio::Mode::Alt0),
+    (2, 14, rppal::gpio::Mode::Alt0),
+    (3, 15, rppal::gpio::Mode::Alt0),
+    (2, 18, rppal::gpio::Mode::Alt0),
+    (3, 19, rppal::gpio::Mode::Alt0),
+];
+
+/// Returns the hardware PWM channel the board routes to a pin, the channels are only usable when enabled in the device tree
+fn get_pwm_channel(channel: u8) -> Option<rppal::pwm::Channel> {
+    match channel {
+        0 => Some(rppal::pwm::Channel::Pwm0),
+        1 => Some(rppal::pwm::Channel::Pwm1),
+        2 => Some(rppal::pwm::Channel::Pwm2),
+        3 => Some(rppal::pwm::Channel::Pwm3),
+        _ => None,
+    }
+}
+
+/// Returns true when the pinmux routes `channel` to `bcm` and to no other GPIO, so the channel only drives the granted pin
+fn is_routed_to(gpio: &rppal::gpio::Gpio, channel: u8, bcm: u8) -> bool {
+    let routes = match rppal::system::DeviceInfo::new().map(|info| info.soc()) {
+        Ok(rppal::system::SoC::Bcm2712) => RP1_PWM_ROUTES,
+        Ok(_) => BCM_PWM_ROUTES,
+        Err(_) => return false,
+    };
+
+    let mut granted = false;
+
+    for &(route_channel, route_bcm, function) in routes.iter() {
+        if route_channel != channel {
+            continue;
+        }
+
+        let routed = match gpio.get(route_bcm) {
+            Ok(pin) => pin.mode() == function,
+            // Pins the SoC does not have can not carry the channel
+            Err(rppal::gpio::Error::PinNotAvailable(_)) => false,
+            // A pin in use can not be checked, so it might carry the channel
+            Err(_) => return false,
+        };
+
+        match route_bcm == bcm {
+            true => granted = routed,
+            false if routed => return false,
+            false => {}
+        }
+    }
+
+    granted
+}
+
+/// Returns the hardware PWM channel of `pin` when it only drives that pin and the device tree enables it
+pub(crate) fn get_hardware_pwm(
+    gpio: &rppal::gpio::Gpio,
+    pin: &crate::board::BoardPin,
+) -> Option<rppal::pwm::Pwm> {
+    let channel = pin.pwm?;
+
+    // Hardware PWM drives every GPIO its channel is routed to, software PWM is used unless that is only the granted pin
+    if !is_routed_to(gpio, channel, pin.bcm) {
+        return None;
+    }
+
+    rppal::pwm::Pwm::new(get_pwm_channel(channel)?).ok()
+}
+
+fn get_output(
+    ctx: &crate::ctx::WasiGpioCtx,
+    label: &str,
+) -> Result<ToneOutput, general::GpioError> {
+    let pin = ctx
+        .policies
+        .get_pin(label)
+        .ok_or_else(|| general::GpioError::Other("Pin not found in policy".to_string()))?;
+
+    let gpio = rppal::gpio::Gpio::new().map_err(|e| general::GpioError::Other(e.to_string()))?;
+
+    if let Some(pwm) = get_hardware_pwm(&gpio, pin) {
+        return Ok(ToneOutput::Hardware(pwm));
+    }
+
+    let pin = gpio
+        .get(pin.bcm)
+        .map_err(|e| general::GpioError::Other(e.to_string()))?;
+
+    Ok(ToneOutput::Software(pin.into_output_low()))
+}
+
+impl ToneState {
+    fn new(output: ToneOutput) -> Self {
+        Self {
+            output,
+            playing: false,
+            generation: 0,
+            waiting: Vec::new(),
+        }
+    }
+
+    fn play(&mut self, frequency: f64, duty_cycle: f64) -> Result<u64, String> {
+        match &mut self.output {
+            ToneOutput::Hardware(pwm) => pwm
+                .set_frequency(frequency, duty_cycle)
+                .and_then(|_| pwm.enable())
+                .map_err(|err| err.to_string())?,
+            ToneOutput::Software(pin) => pin
+                .set_pwm_frequency(frequency, duty_cycle)
+                .map_err(|err| err.to_string())?,
+        }
+
+        self.playing = true;
+        self.generation += 1;
+
+        Ok(self.generation)
+    }
+
+    fn stop(&mut self) -> Result<(), String> {
+        match &mut self.output {
+            ToneOutput::Hardware(pwm) => pwm.disable().map_err(|err| err.to_string())?,
+            ToneOutput::Software(pin) => {
+                pin.clear_pwm().map_err(|err| err.to_string())?;
+                pin.set_low();
+            }
+        }
+
+        self.finish();
+
+        Ok(())
+    }
+
+    /// Marks the tone as done without touching the output
+    fn finish(&mut self) {
+        self.playing = false;
+
+        for trigger in self.waiting.drain(..) {
+            trigger.set();
+        }
+    }
+
+    /// Leaves the output as it is when dropped instead of resetting it, for pins held by their safe state
+    fn keep_state(&mut self) {
+        match &mut self.output {
+            ToneOutput::Hardware(pwm) => pwm.set_reset_on_drop(false),
+            ToneOutput::Software(pin) => pin.set_reset_on_drop(false),
+        }
+    }
+
+    /// Returns a trigger that is set once no tone is playing
+    fn watch_done(&mut self) -> Trigger {
+        let trigger = Trigger::new(!self.playing);
+
+        if self.playing {
+            self.waiting.push(trigger.clone());
+        }
+
+        trigger
+    }
+}
+
+impl Tone {
+    pub fn new(output: ToneOutput, constraints: policies::PinConstraints, label: String) -> Self {
+        Self {
+            state: Shared::make_shared(ToneState::new(output)),
+            constraints,
+            label,
+        }
+    }
+
+    pub fn play(
+        &self,
+        frequency: f32,
+        duration: Option<std::time::Duration>,
+    ) -> Result<(), String> {
+        if !self.constraints.allows_frequency(frequency as f64) {
+            return Err("Frequency outside the range allowed by the policy".to_string());
+        }
+
+        let duty_cycle = self.constraints.limit_duty_cycle(0.5);
+        let generation = self
+            .state
+            .lock()
+            .unwrap()
+            .play(frequency as f64, duty_cycle)?;
+
+        if let Some(duration) = duration {
+            // A weak reference lets a dropped tone release its pin while the timer still sleeps
+            let state_weak = std::sync::Arc::downgrade(&self.state);
+
+            std::thread::spawn(move || {
+                std::thread::sleep(duration);
+
+                let Some(state) = state_weak.upgrade() else {
+                    return;
+                };
+
+                let mut state = state.lock().unwrap();
+                if state.playing && state.generation == generation {
+                    let _ = state.stop();
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a, T: WasiGpioView> tone::Host for GpioImpl<'a, T> {}
+
+impl<'a, T: WasiGpioView> tone::HostTone for GpioImpl<'a, T> {
+    fn get(&mut self, pin_label: String) -> Result<Resource<Tone>, general::GpioError> {
+        if !self
+            .ctx()
+            .policies
+            .is_mode_allowed(&pin_label, policies::Mode::DigitalOutput)
+        {
+            return Err(general::GpioError::PinModeNotAllowed);
+        }
+
+        // Timed tones stop on their own thread, past the rate limit and interlocks
+        if self.ctx().policies.is_output_guarded(&pin_label) {
+            return Err(general::GpioError::PinModeNotAllowed);
+        }
+
+        let output = get_output(self.ctx(), &pin_label)?;
+        let constraints = self
+            .ctx()
+            .policies
+            .get_constraints(&pin_label)
+            .unwrap_or_default();
+
+        let tone = Tone::new(output, constraints, pin_label);
+
+        if self.keeps_state(&tone.label) {
+            tone.state.lock().unwrap().keep_state();
+        }
+
+        self.table()
+            .push(tone)
+            .map_err(|err| general::GpioError::Other(err.to_string()))
+    }
+
+    fn play(
+        &mut self,
+        self_: Resource<Tone>,
+        frequency: f32,
+        duration_ms: Option<u64>,
+    ) -> Result<(), general::GpioError> {
+        if !frequency.is_finite() || frequency <= 0. {
+            return Err(general::GpioError::Other("Invalid frequency".to_string()));
+        }
+
+        self.table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .play(frequency, duration_ms.map(std::time::Duration::from_millis))
+            .map_err(general::GpioError::Other)
+    }
+
+    fn stop(&mut self, self_: Resource<Tone>) -> Result<(), general::GpioError> {
+        self.table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .state
+            .lock()
+            .unwrap()
+            .stop()
+            .map_err(general::GpioError::Other)
+    }
+
+    fn is_playing(&mut self, self_: Resource<Tone>) -> Result<bool, general::GpioError> {
+        Ok(self
+            .table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .state
+            .lock()
+            .unwrap()
+            .playing)
+    }
+
+    fn watch_done(
+        &mut self,
+        self_: Resource<Tone>,
+    ) -> Result<Resource<poll::PollableResource>, general::GpioError> {
+        let trigger = self
+            .table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .state
+            .lock()
+            .unwrap()
+            .watch_done();
+
+        self.push_pollable(poll::Pollable::new(trigger))
+            .map_err(|err| general::GpioError::Other(err.to_string()))
+    }
+
+    fn drop(&mut self, rep: Resource<Tone>) -> wasmtime::Result<()> {
+        let tone = self.table().delete(rep).expect("failed to delete resource");
+        let label = tone.label.clone();
+
+        // A held pin is left as the tone had it, stopping would drive it low first
+        let mut state = tone.state.lock().unwrap();
+        match self.keeps_state(&label) {
+            true => state.finish(),
+            false => {
+                let _ = state.stop();
+            }
+        }
+        std::mem::drop(state);
+        std::mem::drop(tone);
+        self.apply_safe_state(&label)
+    }
+}
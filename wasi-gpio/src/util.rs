@@ -9,3 +9,100 @@ impl<T> SharedExt<T> for Shared<T> {
         std::sync::Arc::new(std::sync::Mutex::new(value))
     }
 }
+
+/// Flag that pollables wait on, setting it wakes up every async waiter
+#[derive(Clone)]
+pub struct Trigger {
+    state: std::sync::Arc<TriggerState>,
+}
+
+struct TriggerState {
+    set: std::sync::atomic::AtomicBool,
+    notify: tokio::sync::Notify,
+}
+
+impl Trigger {
+    pub fn new(set: bool) -> Self {
+        Self {
+            state: std::sync::Arc::new(TriggerState {
+                set: std::sync::atomic::AtomicBool::new(set),
+                notify: tokio::sync::Notify::new(),
+            }),
+        }
+    }
+
+    pub fn set(&self) {
+        self.state
+            .set
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        self.state.notify.notify_waiters();
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.state.set.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Returns a future that completes on the next `set`, has to be created before checking `is_set`
+    pub fn notified(&self) -> tokio::sync::futures::Notified<'_> {
+        self.state.notify.notified()
+    }
+}
+
+/// Longest time a blocking call waits before checking its cancel token again
+pub const CANCEL_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// Lets the host stop blocking calls of a component, e.g. to terminate it or to enforce a timeout
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Returns `Err(Cancelled)` once the token is cancelled, to be used with `?` in blocking loops
+    pub fn check(&self) -> Result<(), Cancelled> {
+        match self.is_cancelled() {
+            true => Err(Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Trap raised in the component when a blocking call got cancelled by the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "blocking call cancelled by the host")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Moves the calling thread to the SCHED_FIFO realtime class, fails without the needed privileges
+pub fn set_realtime_priority() -> std::io::Result<()> {
+    // SAFETY: sched_param is a plain C struct and the thread handle refers to the calling thread
+    let result = unsafe {
+        let mut param: libc::sched_param = std::mem::zeroed();
+        param.sched_priority = libc::sched_get_priority_max(libc::SCHED_FIFO) / 2;
+        libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param)
+    };
+
+    match result {
+        0 => Ok(()),
+        errno => Err(std::io::Error::from_raw_os_error(errno)),
+    }
+}
@@ -0,0 +1,898 @@
+//! Bindings for components built against `wasi:gpio@0.2.0`, vendored in the `wit-0.2.0` folder.
+//! Version 0.2.1 added `rate-limited` to `gpio-error` and `servo` to the analog output modes and flags, so the interfaces that use them are defined for 0.2.0 as well and every call is handed to the 0.2.1 implementation.
+//! The unchanged `delay` and `poll` of 0.2.0 components link to the definitions of 0.2.1, which wasmtime allows for compatible versions.
+
+use crate::analog::{AnalogInOutPin, AnalogInPin, AnalogOutPin};
+use crate::ctx::WasiGpioView;
+use crate::digital::{DigitalInOutPin, DigitalInPin, DigitalOutPin, StatefulDigitalOutPin};
+use crate::impls::GpioImpl;
+use crate::poll::PollableResource;
+use crate::wasi::gpio as current;
+use wasmtime::component::{Linker, Resource};
+
+wasmtime::component::bindgen!({
+    path: "../wit-0.2.0",
+    world: "rpi",
+    with: {
+        "wasi:gpio/digital.digital-out-pin": crate::digital::DigitalOutPin,
+        "wasi:gpio/digital.digital-in-pin": crate::digital::DigitalInPin,
+        "wasi:gpio/digital.digital-in-out-pin": crate::digital::DigitalInOutPin,
+        "wasi:gpio/digital.stateful-digital-out-pin": crate::digital::StatefulDigitalOutPin,
+
+        "wasi:gpio/analog.analog-in-pin": crate::analog::AnalogInPin,
+        "wasi:gpio/analog.analog-out-pin": crate::analog::AnalogOutPin,
+        "wasi:gpio/analog.analog-in-out-pin": crate::analog::AnalogInOutPin,
+
+        "wasi:gpio/delay": crate::wasi::gpio::delay,
+        "wasi:gpio/poll": crate::wasi::gpio::poll,
+    }
+});
+
+use self::wasi::gpio::{analog, digital, general};
+
+impl From<current::general::GpioError> for general::GpioError {
+    fn from(error: current::general::GpioError) -> Self {
+        match error {
+            current::general::GpioError::HardwareFault => Self::HardwareFault,
+            current::general::GpioError::AlreadyInUse => Self::AlreadyInUse,
+            current::general::GpioError::PinNotAllowed => Self::PinNotAllowed,
+            current::general::GpioError::PinModeNotAllowed => Self::PinModeNotAllowed,
+            current::general::GpioError::UndefinedPinLabel => Self::UndefinedPinLabel,
+            current::general::GpioError::PinModeNotAvailable => Self::PinModeNotAvailable,
+            current::general::GpioError::WouldBlock => Self::WouldBlock,
+            current::general::GpioError::OperationNotSupported => Self::OperationNotSupported,
+            current::general::GpioError::InvalidFlag => Self::InvalidFlag,
+            current::general::GpioError::MissingFlags => Self::MissingFlags,
+            current::general::GpioError::ResourceInvalidated => Self::ResourceInvalidated,
+            // Rate limits came with 0.2.1, the message tells them apart
+            current::general::GpioError::RateLimited => {
+                Self::Other("Pin changed more often than the policy allows".to_string())
+            }
+            current::general::GpioError::Other(message) => Self::Other(message),
+        }
+    }
+}
+
+impl From<current::general::PinMode> for general::PinMode {
+    fn from(pin_mode: current::general::PinMode) -> Self {
+        match pin_mode {
+            current::general::PinMode::In => Self::In,
+            current::general::PinMode::Out => Self::Out,
+        }
+    }
+}
+
+impl From<general::PinMode> for current::general::PinMode {
+    fn from(pin_mode: general::PinMode) -> Self {
+        match pin_mode {
+            general::PinMode::In => Self::In,
+            general::PinMode::Out => Self::Out,
+        }
+    }
+}
+
+impl From<current::general::ActiveLevel> for general::ActiveLevel {
+    fn from(active_level: current::general::ActiveLevel) -> Self {
+        match active_level {
+            current::general::ActiveLevel::ActiveHigh => Self::ActiveHigh,
+            current::general::ActiveLevel::ActiveLow => Self::ActiveLow,
+        }
+    }
+}
+
+impl From<current::general::PullResistor> for general::PullResistor {
+    fn from(pull_resistor: current::general::PullResistor) -> Self {
+        match pull_resistor {
+            current::general::PullResistor::PullUp => Self::PullUp,
+            current::general::PullResistor::PullDown => Self::PullDown,
+        }
+    }
+}
+
+impl From<current::digital::PinState> for digital::PinState {
+    fn from(state: current::digital::PinState) -> Self {
+        match state {
+            current::digital::PinState::Active => Self::Active,
+            current::digital::PinState::Inactive => Self::Inactive,
+        }
+    }
+}
+
+impl From<digital::PinState> for current::digital::PinState {
+    fn from(state: digital::PinState) -> Self {
+        match state {
+            digital::PinState::Active => Self::Active,
+            digital::PinState::Inactive => Self::Inactive,
+        }
+    }
+}
+
+impl From<current::digital::DigitalConfig> for digital::DigitalConfig {
+    fn from(config: current::digital::DigitalConfig) -> Self {
+        Self {
+            label: config.label,
+            pin_mode: config.pin_mode.into(),
+            active_level: config.active_level.into(),
+            pull_resistor: config.pull_resistor.map(Into::into),
+        }
+    }
+}
+
+impl From<current::analog::AnalogConfig> for analog::AnalogConfig {
+    fn from(config: current::analog::AnalogConfig) -> Self {
+        Self {
+            label: config.label,
+            pin_mode: config.pin_mode.into(),
+            output_mode: config.output_mode.map(|output_mode| match output_mode {
+                current::analog::OutputMode::Dac => analog::OutputMode::Dac,
+                // Servos came with 0.2.1, their pulses are PWM all the same
+                current::analog::OutputMode::Pwm | current::analog::OutputMode::Servo => {
+                    analog::OutputMode::Pwm
+                }
+            }),
+        }
+    }
+}
+
+fn digital_flags(flags: Vec<digital::DigitalFlag>) -> Vec<current::digital::DigitalFlag> {
+    const FLAGS: [(digital::DigitalFlag, current::digital::DigitalFlag); 8] = [
+        (
+            digital::DigitalFlag::ACTIVE_HIGH,
+            current::digital::DigitalFlag::ACTIVE_HIGH,
+        ),
+        (
+            digital::DigitalFlag::ACTIVE_LOW,
+            current::digital::DigitalFlag::ACTIVE_LOW,
+        ),
+        (
+            digital::DigitalFlag::PULL_UP,
+            current::digital::DigitalFlag::PULL_UP,
+        ),
+        (
+            digital::DigitalFlag::PULL_DOWN,
+            current::digital::DigitalFlag::PULL_DOWN,
+        ),
+        (
+            digital::DigitalFlag::ACTIVE,
+            current::digital::DigitalFlag::ACTIVE,
+        ),
+        (
+            digital::DigitalFlag::INACTIVE,
+            current::digital::DigitalFlag::INACTIVE,
+        ),
+        (
+            digital::DigitalFlag::INPUT,
+            current::digital::DigitalFlag::INPUT,
+        ),
+        (
+            digital::DigitalFlag::OUTPUT,
+            current::digital::DigitalFlag::OUTPUT,
+        ),
+    ];
+
+    flags
+        .into_iter()
+        .map(|flag| {
+            FLAGS
+                .iter()
+                .filter(|(old, _)| flag.contains(*old))
+                .fold(current::digital::DigitalFlag::empty(), |acc, (_, new)| {
+                    acc | *new
+                })
+        })
+        .collect()
+}
+
+fn analog_flags(flags: Vec<analog::AnalogFlag>) -> Vec<current::analog::AnalogFlag> {
+    const FLAGS: [(analog::AnalogFlag, current::analog::AnalogFlag); 2] = [
+        (analog::AnalogFlag::DAC, current::analog::AnalogFlag::DAC),
+        (analog::AnalogFlag::PWM, current::analog::AnalogFlag::PWM),
+    ];
+
+    flags
+        .into_iter()
+        .map(|flag| {
+            FLAGS
+                .iter()
+                .filter(|(old, _)| flag.contains(*old))
+                .fold(current::analog::AnalogFlag::empty(), |acc, (_, new)| {
+                    acc | *new
+                })
+        })
+        .collect()
+}
+
+impl<T: WasiGpioView> general::Host for GpioImpl<'_, T> {}
+
+impl<T: WasiGpioView> digital::Host for GpioImpl<'_, T> {}
+
+impl<T: WasiGpioView> digital::HostDigitalOutPin for GpioImpl<'_, T> {
+    fn get(
+        &mut self,
+        pin_label: String,
+        flags: Vec<digital::DigitalFlag>,
+    ) -> Result<Resource<DigitalOutPin>, general::GpioError> {
+        Ok(current::digital::HostDigitalOutPin::get(
+            self,
+            pin_label,
+            digital_flags(flags),
+        )?)
+    }
+
+    fn get_config(
+        &mut self,
+        self_: Resource<DigitalOutPin>,
+    ) -> Result<digital::DigitalConfig, general::GpioError> {
+        Ok(current::digital::HostDigitalOutPin::get_config(self, self_)?.into())
+    }
+
+    fn is_ready(&mut self, self_: Resource<DigitalOutPin>) -> bool {
+        current::digital::HostDigitalOutPin::is_ready(self, self_)
+    }
+
+    fn set_state(
+        &mut self,
+        self_: Resource<DigitalOutPin>,
+        state: digital::PinState,
+    ) -> Result<(), general::GpioError> {
+        Ok(current::digital::HostDigitalOutPin::set_state(
+            self,
+            self_,
+            state.into(),
+        )?)
+    }
+
+    fn set_active(&mut self, self_: Resource<DigitalOutPin>) -> Result<(), general::GpioError> {
+        Ok(current::digital::HostDigitalOutPin::set_active(
+            self, self_,
+        )?)
+    }
+
+    fn set_inactive(&mut self, self_: Resource<DigitalOutPin>) -> Result<(), general::GpioError> {
+        Ok(current::digital::HostDigitalOutPin::set_inactive(
+            self, self_,
+        )?)
+    }
+
+    fn drop(&mut self, rep: Resource<DigitalOutPin>) -> wasmtime::Result<()> {
+        current::digital::HostDigitalOutPin::drop(self, rep)
+    }
+}
+
+impl<T: WasiGpioView> digital::HostStatefulDigitalOutPin for GpioImpl<'_, T> {
+    fn get(
+        &mut self,
+        pin_label: String,
+        flags: Vec<digital::DigitalFlag>,
+    ) -> Result<Resource<StatefulDigitalOutPin>, general::GpioError> {
+        Ok(current::digital::HostStatefulDigitalOutPin::get(
+            self,
+            pin_label,
+            digital_flags(flags),
+        )?)
+    }
+
+    fn get_config(
+        &mut self,
+        self_: Resource<StatefulDigitalOutPin>,
+    ) -> Result<digital::DigitalConfig, general::GpioError> {
+        Ok(current::digital::HostStatefulDigitalOutPin::get_config(self, self_)?.into())
+    }
+
+    fn is_ready(&mut self, self_: Resource<StatefulDigitalOutPin>) -> bool {
+        current::digital::HostStatefulDigitalOutPin::is_ready(self, self_)
+    }
+
+    fn set_state(
+        &mut self,
+        self_: Resource<StatefulDigitalOutPin>,
+        state: digital::PinState,
+    ) -> Result<(), general::GpioError> {
+        Ok(current::digital::HostStatefulDigitalOutPin::set_state(
+            self,
+            self_,
+            state.into(),
+        )?)
+    }
+
+    fn set_active(
+        &mut self,
+        self_: Resource<StatefulDigitalOutPin>,
+    ) -> Result<(), general::GpioError> {
+        Ok(current::digital::HostStatefulDigitalOutPin::set_active(
+            self, self_,
+        )?)
+    }
+
+    fn set_inactive(
+        &mut self,
+        self_: Resource<StatefulDigitalOutPin>,
+    ) -> Result<(), general::GpioError> {
+        Ok(current::digital::HostStatefulDigitalOutPin::set_inactive(
+            self, self_,
+        )?)
+    }
+
+    fn toggle(&mut self, self_: Resource<StatefulDigitalOutPin>) -> Result<(), general::GpioError> {
+        Ok(current::digital::HostStatefulDigitalOutPin::toggle(
+            self, self_,
+        )?)
+    }
+
+    fn is_set_active(
+        &mut self,
+        self_: Resource<StatefulDigitalOutPin>,
+    ) -> Result<bool, general::GpioError> {
+        Ok(current::digital::HostStatefulDigitalOutPin::is_set_active(
+            self, self_,
+        )?)
+    }
+
+    fn is_set_inactive(
+        &mut self,
+        self_: Resource<StatefulDigitalOutPin>,
+    ) -> Result<bool, general::GpioError> {
+        Ok(current::digital::HostStatefulDigitalOutPin::is_set_inactive(self, self_)?)
+    }
+
+    fn get_state(
+        &mut self,
+        self_: Resource<StatefulDigitalOutPin>,
+    ) -> Result<digital::PinState, general::GpioError> {
+        Ok(current::digital::HostStatefulDigitalOutPin::get_state(self, self_)?.into())
+    }
+
+    fn drop(&mut self, rep: Resource<StatefulDigitalOutPin>) -> wasmtime::Result<()> {
+        current::digital::HostStatefulDigitalOutPin::drop(self, rep)
+    }
+}
+
+impl<T: WasiGpioView> digital::HostDigitalInPin for GpioImpl<'_, T> {
+    fn get(
+        &mut self,
+        pin_label: String,
+        flags: Vec<digital::DigitalFlag>,
+    ) -> Result<Resource<DigitalInPin>, general::GpioError> {
+        Ok(current::digital::HostDigitalInPin::get(
+            self,
+            pin_label,
+            digital_flags(flags),
+        )?)
+    }
+
+    fn get_config(
+        &mut self,
+        self_: Resource<DigitalInPin>,
+    ) -> Result<digital::DigitalConfig, general::GpioError> {
+        Ok(current::digital::HostDigitalInPin::get_config(self, self_)?.into())
+    }
+
+    fn is_ready(&mut self, self_: Resource<DigitalInPin>) -> bool {
+        current::digital::HostDigitalInPin::is_ready(self, self_)
+    }
+
+    fn read(
+        &mut self,
+        self_: Resource<DigitalInPin>,
+    ) -> Result<digital::PinState, general::GpioError> {
+        Ok(current::digital::HostDigitalInPin::read(self, self_)?.into())
+    }
+
+    fn is_active(&mut self, self_: Resource<DigitalInPin>) -> Result<bool, general::GpioError> {
+        Ok(current::digital::HostDigitalInPin::is_active(self, self_)?)
+    }
+
+    fn is_inactive(&mut self, self_: Resource<DigitalInPin>) -> Result<bool, general::GpioError> {
+        Ok(current::digital::HostDigitalInPin::is_inactive(
+            self, self_,
+        )?)
+    }
+
+    fn watch_state(
+        &mut self,
+        self_: Resource<DigitalInPin>,
+        state: digital::PinState,
+    ) -> Result<Resource<PollableResource>, general::GpioError> {
+        Ok(current::digital::HostDigitalInPin::watch_state(
+            self,
+            self_,
+            state.into(),
+        )?)
+    }
+
+    fn watch_active(
+        &mut self,
+        self_: Resource<DigitalInPin>,
+    ) -> Result<Resource<PollableResource>, general::GpioError> {
+        Ok(current::digital::HostDigitalInPin::watch_active(
+            self, self_,
+        )?)
+    }
+
+    fn watch_inactive(
+        &mut self,
+        self_: Resource<DigitalInPin>,
+    ) -> Result<Resource<PollableResource>, general::GpioError> {
+        Ok(current::digital::HostDigitalInPin::watch_inactive(
+            self, self_,
+        )?)
+    }
+
+    fn watch_falling_edge(
+        &mut self,
+        self_: Resource<DigitalInPin>,
+    ) -> Result<Resource<PollableResource>, general::GpioError> {
+        Ok(current::digital::HostDigitalInPin::watch_falling_edge(
+            self, self_,
+        )?)
+    }
+
+    fn watch_rising_edge(
+        &mut self,
+        self_: Resource<DigitalInPin>,
+    ) -> Result<Resource<PollableResource>, general::GpioError> {
+        Ok(current::digital::HostDigitalInPin::watch_rising_edge(
+            self, self_,
+        )?)
+    }
+
+    fn drop(&mut self, rep: Resource<DigitalInPin>) -> wasmtime::Result<()> {
+        current::digital::HostDigitalInPin::drop(self, rep)
+    }
+}
+
+impl<T: WasiGpioView> digital::HostDigitalInOutPin for GpioImpl<'_, T> {
+    fn get(
+        &mut self,
+        pin_label: String,
+        flags: Vec<digital::DigitalFlag>,
+    ) -> Result<Resource<DigitalInOutPin>, general::GpioError> {
+        Ok(current::digital::HostDigitalInOutPin::get(
+            self,
+            pin_label,
+            digital_flags(flags),
+        )?)
+    }
+
+    fn get_config(
+        &mut self,
+        self_: Resource<DigitalInOutPin>,
+    ) -> Result<digital::DigitalConfig, general::GpioError> {
+        Ok(current::digital::HostDigitalInOutPin::get_config(self, self_)?.into())
+    }
+
+    fn is_ready(&mut self, self_: Resource<DigitalInOutPin>) -> bool {
+        current::digital::HostDigitalInOutPin::is_ready(self, self_)
+    }
+
+    fn set_state(
+        &mut self,
+        self_: Resource<DigitalInOutPin>,
+        state: digital::PinState,
+    ) -> Result<(), general::GpioError> {
+        Ok(current::digital::HostDigitalInOutPin::set_state(
+            self,
+            self_,
+            state.into(),
+        )?)
+    }
+
+    fn set_active(&mut self, self_: Resource<DigitalInOutPin>) -> Result<(), general::GpioError> {
+        Ok(current::digital::HostDigitalInOutPin::set_active(
+            self, self_,
+        )?)
+    }
+
+    fn set_inactive(&mut self, self_: Resource<DigitalInOutPin>) -> Result<(), general::GpioError> {
+        Ok(current::digital::HostDigitalInOutPin::set_inactive(
+            self, self_,
+        )?)
+    }
+
+    fn read(
+        &mut self,
+        self_: Resource<DigitalInOutPin>,
+    ) -> Result<digital::PinState, general::GpioError> {
+        Ok(current::digital::HostDigitalInOutPin::read(self, self_)?.into())
+    }
+
+    fn is_active(&mut self, self_: Resource<DigitalInOutPin>) -> Result<bool, general::GpioError> {
+        Ok(current::digital::HostDigitalInOutPin::is_active(
+            self, self_,
+        )?)
+    }
+
+    fn is_inactive(
+        &mut self,
+        self_: Resource<DigitalInOutPin>,
+    ) -> Result<bool, general::GpioError> {
+        Ok(current::digital::HostDigitalInOutPin::is_inactive(
+            self, self_,
+        )?)
+    }
+
+    fn set_pin_mode(
+        &mut self,
+        self_: Resource<DigitalInOutPin>,
+        pin_mode: general::PinMode,
+    ) -> Result<(), general::GpioError> {
+        Ok(current::digital::HostDigitalInOutPin::set_pin_mode(
+            self,
+            self_,
+            pin_mode.into(),
+        )?)
+    }
+
+    fn drop(&mut self, rep: Resource<DigitalInOutPin>) -> wasmtime::Result<()> {
+        current::digital::HostDigitalInOutPin::drop(self, rep)
+    }
+}
+
+impl<T: WasiGpioView> analog::Host for GpioImpl<'_, T> {}
+
+impl<T: WasiGpioView> analog::HostAnalogOutPin for GpioImpl<'_, T> {
+    fn get(
+        &mut self,
+        pin_label: String,
+        flags: Vec<analog::AnalogFlag>,
+    ) -> Result<Resource<AnalogOutPin>, general::GpioError> {
+        Ok(current::analog::HostAnalogOutPin::get(
+            self,
+            pin_label,
+            analog_flags(flags),
+        )?)
+    }
+
+    fn get_config(
+        &mut self,
+        self_: Resource<AnalogOutPin>,
+    ) -> Result<analog::AnalogConfig, general::GpioError> {
+        Ok(current::analog::HostAnalogOutPin::get_config(self, self_)?.into())
+    }
+
+    fn is_ready(&mut self, self_: Resource<AnalogOutPin>) -> bool {
+        current::analog::HostAnalogOutPin::is_ready(self, self_)
+    }
+
+    fn set_value_raw(
+        &mut self,
+        self_: Resource<AnalogOutPin>,
+        value: u32,
+    ) -> Result<(), general::GpioError> {
+        Ok(current::analog::HostAnalogOutPin::set_value_raw(
+            self, self_, value,
+        )?)
+    }
+
+    fn set_value(
+        &mut self,
+        self_: Resource<AnalogOutPin>,
+        value: f32,
+    ) -> Result<(), general::GpioError> {
+        Ok(current::analog::HostAnalogOutPin::set_value(
+            self, self_, value,
+        )?)
+    }
+
+    fn drop(&mut self, rep: Resource<AnalogOutPin>) -> wasmtime::Result<()> {
+        current::analog::HostAnalogOutPin::drop(self, rep)
+    }
+}
+
+impl<T: WasiGpioView> analog::HostAnalogInPin for GpioImpl<'_, T> {
+    fn get(
+        &mut self,
+        pin_label: String,
+        flags: Vec<analog::AnalogFlag>,
+    ) -> Result<Resource<AnalogInPin>, general::GpioError> {
+        Ok(current::analog::HostAnalogInPin::get(
+            self,
+            pin_label,
+            analog_flags(flags),
+        )?)
+    }
+
+    fn get_config(
+        &mut self,
+        self_: Resource<AnalogInPin>,
+    ) -> Result<analog::AnalogConfig, general::GpioError> {
+        Ok(current::analog::HostAnalogInPin::get_config(self, self_)?.into())
+    }
+
+    fn is_ready(&mut self, self_: Resource<AnalogInPin>) -> bool {
+        current::analog::HostAnalogInPin::is_ready(self, self_)
+    }
+
+    fn read_raw(&mut self, self_: Resource<AnalogInPin>) -> Result<u32, general::GpioError> {
+        Ok(current::analog::HostAnalogInPin::read_raw(self, self_)?)
+    }
+
+    fn read(&mut self, self_: Resource<AnalogInPin>) -> Result<f32, general::GpioError> {
+        Ok(current::analog::HostAnalogInPin::read(self, self_)?)
+    }
+
+    fn watch_above_raw(
+        &mut self,
+        self_: Resource<AnalogInPin>,
+        value: u32,
+    ) -> Result<Resource<PollableResource>, general::GpioError> {
+        Ok(current::analog::HostAnalogInPin::watch_above_raw(
+            self, self_, value,
+        )?)
+    }
+
+    fn watch_above(
+        &mut self,
+        self_: Resource<AnalogInPin>,
+        value: f32,
+    ) -> Result<Resource<PollableResource>, general::GpioError> {
+        Ok(current::analog::HostAnalogInPin::watch_above(
+            self, self_, value,
+        )?)
+    }
+
+    fn watch_below_raw(
+        &mut self,
+        self_: Resource<AnalogInPin>,
+        value: u32,
+    ) -> Result<Resource<PollableResource>, general::GpioError> {
+        Ok(current::analog::HostAnalogInPin::watch_below_raw(
+            self, self_, value,
+        )?)
+    }
+
+    fn watch_below(
+        &mut self,
+        self_: Resource<AnalogInPin>,
+        value: f32,
+    ) -> Result<Resource<PollableResource>, general::GpioError> {
+        Ok(current::analog::HostAnalogInPin::watch_below(
+            self, self_, value,
+        )?)
+    }
+
+    fn drop(&mut self, rep: Resource<AnalogInPin>) -> wasmtime::Result<()> {
+        current::analog::HostAnalogInPin::drop(self, rep)
+    }
+}
+
+impl<T: WasiGpioView> analog::HostAnalogInOutPin for GpioImpl<'_, T> {
+    fn get(
+        &mut self,
+        pin_label: String,
+        flags: Vec<analog::AnalogFlag>,
+    ) -> Result<Resource<AnalogInOutPin>, general::GpioError> {
+        Ok(current::analog::HostAnalogInOutPin::get(
+            self,
+            pin_label,
+            analog_flags(flags),
+        )?)
+    }
+
+    fn get_config(
+        &mut self,
+        self_: Resource<AnalogInOutPin>,
+    ) -> Result<analog::AnalogConfig, general::GpioError> {
+        Ok(current::analog::HostAnalogInOutPin::get_config(self, self_)?.into())
+    }
+
+    fn is_ready(&mut self, self_: Resource<AnalogInOutPin>) -> bool {
+        current::analog::HostAnalogInOutPin::is_ready(self, self_)
+    }
+
+    fn set_value_raw(
+        &mut self,
+        self_: Resource<AnalogInOutPin>,
+        value: u32,
+    ) -> Result<(), general::GpioError> {
+        Ok(current::analog::HostAnalogInOutPin::set_value_raw(
+            self, self_, value,
+        )?)
+    }
+
+    fn set_value(
+        &mut self,
+        self_: Resource<AnalogInOutPin>,
+        value: f32,
+    ) -> Result<(), general::GpioError> {
+        Ok(current::analog::HostAnalogInOutPin::set_value(
+            self, self_, value,
+        )?)
+    }
+
+    fn read_raw(&mut self, self_: Resource<AnalogInOutPin>) -> Result<u32, general::GpioError> {
+        Ok(current::analog::HostAnalogInOutPin::read_raw(self, self_)?)
+    }
+
+    fn read(&mut self, self_: Resource<AnalogInOutPin>) -> Result<f32, general::GpioError> {
+        Ok(current::analog::HostAnalogInOutPin::read(self, self_)?)
+    }
+
+    fn set_pin_mode(
+        &mut self,
+        self_: Resource<AnalogInOutPin>,
+        pin_mode: general::PinMode,
+    ) -> Result<(), general::GpioError> {
+        Ok(current::analog::HostAnalogInOutPin::set_pin_mode(
+            self,
+            self_,
+            pin_mode.into(),
+        )?)
+    }
+
+    fn drop(&mut self, rep: Resource<AnalogInOutPin>) -> wasmtime::Result<()> {
+        current::analog::HostAnalogInOutPin::drop(self, rep)
+    }
+}
+
+/// Adds the interfaces of `wasi:gpio@0.2.0` whose types differ from 0.2.1 to `linker`
+pub(crate) fn add_to_linker<T>(linker: &mut Linker<T>) -> anyhow::Result<()>
+where
+    T: WasiGpioView + 'static,
+{
+    general::add_to_linker::<T, crate::GpioBindingMarker<T>>(linker, |host| GpioImpl { host })?;
+    digital::add_to_linker::<T, crate::GpioBindingMarker<T>>(linker, |host| GpioImpl { host })?;
+    analog::add_to_linker::<T, crate::GpioBindingMarker<T>>(linker, |host| GpioImpl { host })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analog, current, general};
+    use crate::io_bindings::tests::TestHost;
+    use wasmtime::component::{Component, Linker};
+    use wasmtime::{Engine, Store};
+
+    /// Component of `version` that imports `watch-active` of a digital input and `delay-ms`, its `run` export takes the milliseconds to wait
+    ///
+    /// `extra_cases` are spliced into `gpio-error`, which has no `rate-limited` before 0.2.1.
+    fn component(version: &str, extra_cases: &str) -> String {
+        format!(
+            r#"
+            (component $c
+                (type $gpio-error (variant
+                    (case "hardware-fault") (case "already-in-use") (case "pin-not-allowed")
+                    (case "pin-mode-not-allowed") (case "undefined-pin-label") (case "pin-mode-not-available")
+                    (case "would-block") (case "operation-not-supported") (case "invalid-flag")
+                    (case "missing-flags") (case "resource-invalidated") {extra_cases} (case "other" string)))
+                (import "wasi:gpio/general@{version}" (instance $general
+                    (export "gpio-error" (type (eq $gpio-error)))
+                ))
+                (alias export $general "gpio-error" (type $error))
+                (import "wasi:gpio/poll@{version}" (instance $poll
+                    (export "pollable" (type (sub resource)))
+                ))
+                (alias export $poll "pollable" (type $pollable))
+                (import "wasi:gpio/digital@{version}" (instance
+                    (alias outer $c $error (type $e))
+                    (export "gpio-error" (type $error (eq $e)))
+                    (alias outer $c $pollable (type $p))
+                    (export "pollable" (type $pollable (eq $p)))
+                    (export "digital-in-pin" (type $pin (sub resource)))
+                    (type $result (result (own $pollable) (error $error)))
+                    (export "[method]digital-in-pin.watch-active"
+                        (func (param "self" (borrow $pin)) (result $result)))
+                ))
+                (import "wasi:gpio/delay@{version}" (instance $delay
+                    (export "delay-ms" (func (param "ms" u64)))
+                ))
+
+                (core func $delay-ms (canon lower (func $delay "delay-ms")))
+
+                (core module $m
+                    (import "delay" "delay-ms" (func $delay-ms (param i64)))
+                    (func (export "run") (param i64)
+                        (call $delay-ms (local.get 0)))
+                )
+                (core instance $i (instantiate $m
+                    (with "delay" (instance (export "delay-ms" (func $delay-ms))))
+                ))
+
+                (func (export "run") (param "ms" u64) (canon lift (core func $i "run")))
+            )
+            "#
+        )
+    }
+
+    /// Component of `version` that imports the `get` of an analog output, whose `analog-flag` has `flags`
+    fn analog_component(version: &str, flags: &str) -> String {
+        let gpio_error = if version == "0.2.0" {
+            ""
+        } else {
+            r#"(case "rate-limited")"#
+        };
+        format!(
+            r#"
+            (component $c
+                (type $gpio-error (variant
+                    (case "hardware-fault") (case "already-in-use") (case "pin-not-allowed")
+                    (case "pin-mode-not-allowed") (case "undefined-pin-label") (case "pin-mode-not-available")
+                    (case "would-block") (case "operation-not-supported") (case "invalid-flag")
+                    (case "missing-flags") (case "resource-invalidated") {gpio_error} (case "other" string)))
+                (import "wasi:gpio/general@{version}" (instance $general
+                    (export "gpio-error" (type (eq $gpio-error)))
+                ))
+                (alias export $general "gpio-error" (type $error))
+                (import "wasi:gpio/analog@{version}" (instance
+                    (alias outer $c $error (type $e))
+                    (export "gpio-error" (type $error (eq $e)))
+                    (type $flag (flags {flags}))
+                    (export "analog-flag" (type $analog-flag (eq $flag)))
+                    (export "analog-out-pin" (type $pin (sub resource)))
+                    (type $result (result (own $pin) (error $error)))
+                    (export "[static]analog-out-pin.get"
+                        (func (param "pin-label" string) (param "flags" (list $analog-flag)) (result $result)))
+                ))
+            )
+            "#
+        )
+    }
+
+    #[test]
+    fn links_components_of_both_versions() {
+        let engine = Engine::default();
+        let mut linker = Linker::new(&engine);
+        wasmtime_wasi::p2::add_to_linker_sync(&mut linker).unwrap();
+        crate::add_to_linker(&mut linker).unwrap();
+
+        for (version, extra_cases) in [("0.2.0", ""), ("0.2.1", r#"(case "rate-limited")"#)] {
+            let component = Component::new(&engine, component(version, extra_cases)).unwrap();
+            let mut store = Store::new(&engine, TestHost::new());
+            let instance = linker.instantiate(&mut store, &component).unwrap();
+            let run = instance
+                .get_typed_func::<(u64,), ()>(&mut store, "run")
+                .unwrap();
+            run.call(&mut store, (1,)).unwrap();
+        }
+    }
+
+    #[test]
+    fn refuses_components_that_mix_up_the_versions() {
+        let engine = Engine::default();
+        let mut linker = Linker::<TestHost>::new(&engine);
+        wasmtime_wasi::p2::add_to_linker_sync(&mut linker).unwrap();
+        crate::add_to_linker(&mut linker).unwrap();
+
+        for (version, extra_cases) in [("0.2.0", r#"(case "rate-limited")"#), ("0.2.1", "")] {
+            let component = Component::new(&engine, component(version, extra_cases)).unwrap();
+            assert!(linker.instantiate_pre(&component).is_err());
+        }
+    }
+
+    #[test]
+    fn links_analog_components_by_their_flags() {
+        let engine = Engine::default();
+        let mut linker = Linker::<TestHost>::new(&engine);
+        wasmtime_wasi::p2::add_to_linker_sync(&mut linker).unwrap();
+        crate::add_to_linker(&mut linker).unwrap();
+
+        let old_flags = r#""dac" "pwm""#;
+        let new_flags = r#""dac" "pwm" "servo""#;
+        for (version, flags, links) in [
+            ("0.2.0", old_flags, true),
+            ("0.2.1", new_flags, true),
+            ("0.2.0", new_flags, false),
+            ("0.2.1", old_flags, false),
+        ] {
+            let component = Component::new(&engine, analog_component(version, flags)).unwrap();
+            assert_eq!(linker.instantiate_pre(&component).is_ok(), links);
+        }
+    }
+
+    #[test]
+    fn reports_servo_outputs_as_pwm() {
+        let config = analog::AnalogConfig::from(current::analog::AnalogConfig {
+            label: "SERVO".to_string(),
+            pin_mode: current::general::PinMode::Out,
+            output_mode: Some(current::analog::OutputMode::Servo),
+        });
+        assert!(matches!(config.output_mode, Some(analog::OutputMode::Pwm)));
+    }
+
+    #[test]
+    fn reports_rate_limits_as_other_errors() {
+        assert!(matches!(
+            general::GpioError::from(current::general::GpioError::RateLimited),
+            general::GpioError::Other(_)
+        ));
+    }
+}
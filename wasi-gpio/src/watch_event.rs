@@ -1,4 +1,4 @@
-use super::util::{Shared, SharedExt};
+use super::util::{Shared, SharedExt, Trigger};
 use crate::digital::DigitalInPin;
 use std::fmt::Debug;
 
@@ -13,7 +13,7 @@ impl Watcher {
         }
     }
 
-    pub fn watch_event(&mut self, pin: &DigitalInPin, watch_type: WatchType) -> Shared<bool> {
+    pub fn watch_event(&mut self, pin: &DigitalInPin, watch_type: WatchType) -> Trigger {
         let key = WatchEventKey {
             watch_type,
             pin_label: pin.get_config().label.clone(),
@@ -29,7 +29,7 @@ impl Watcher {
             // Make a new watch_event
             None => {
                 //println!("No trigger yet");
-                let trigger = Shared::make_shared(false);
+                let trigger = Trigger::new(false);
 
                 // Food for thread
                 let trigger_clone = trigger.clone();
@@ -43,6 +43,7 @@ impl Watcher {
                         WatchType::Low => watch_low(pin_clone, trigger_clone),
                         WatchType::Rising => watch_rising(pin_clone, trigger_clone),
                         WatchType::Falling => watch_falling(pin_clone, trigger_clone),
+                        WatchType::AnyEdge => watch_any_edge(pin_clone, trigger_clone),
                     };
 
                     (*map_clone.lock().unwrap()).remove(&key_clone);
@@ -62,6 +63,41 @@ impl Watcher {
 
         trigger
     }
+
+    pub fn watch_edges(
+        &mut self,
+        pin: &DigitalInPin,
+        watch_type: WatchType,
+        debounce: std::time::Duration,
+    ) -> Shared<EdgeCount> {
+        let count = Shared::make_shared(EdgeCount::new());
+
+        // Food for thread
+        let count_clone = count.clone();
+        let pin_clone = pin.clone_pin();
+
+        std::thread::spawn(move || count_edges(pin_clone, watch_type, debounce, count_clone));
+
+        count
+    }
+
+    pub fn watch_quadrature(
+        &mut self,
+        pin_a: &DigitalInPin,
+        pin_b: &DigitalInPin,
+        transitions_per_step: u32,
+    ) -> Shared<Quadrature> {
+        let quadrature = Shared::make_shared(Quadrature::new(transitions_per_step));
+
+        // Food for thread
+        let quadrature_clone = quadrature.clone();
+        let pin_a_clone = pin_a.clone_pin();
+        let pin_b_clone = pin_b.clone_pin();
+
+        std::thread::spawn(move || decode_quadrature(pin_a_clone, pin_b_clone, quadrature_clone));
+
+        quadrature
+    }
 }
 
 #[derive(Clone)]
@@ -71,40 +107,293 @@ pub struct WatchEventKey {
 }
 
 pub struct WatchEventValue {
-    trigger: Shared<bool>,
+    trigger: Trigger,
     thread: std::thread::JoinHandle<()>,
 }
 
-fn watch_high(pin: Shared<rppal::gpio::InputPin>, trigger: Shared<bool>) {
+fn watch_high(pin: Shared<rppal::gpio::InputPin>, trigger: Trigger) {
     while (*pin.lock().unwrap()).is_low() {}
 
     //println!("Thread: triggered");
 
-    *trigger.lock().unwrap() = true
+    trigger.set()
 }
 
-fn watch_low(pin: Shared<rppal::gpio::InputPin>, trigger: Shared<bool>) {
+fn watch_low(pin: Shared<rppal::gpio::InputPin>, trigger: Trigger) {
     while (*pin.lock().unwrap()).is_high() {}
 
-    *trigger.lock().unwrap() = true
+    trigger.set()
 }
 
-fn watch_rising(pin: Shared<rppal::gpio::InputPin>, trigger: Shared<bool>) {
+fn watch_rising(pin: Shared<rppal::gpio::InputPin>, trigger: Trigger) {
     // Pin is high so needs to go low first before rising edge can happen
     while (*pin.lock().unwrap()).is_high() {}
     // Pin is low, now check for high event
     while (*pin.lock().unwrap()).is_low() {}
 
-    *trigger.lock().unwrap() = true
+    trigger.set()
 }
 
-fn watch_falling(pin: Shared<rppal::gpio::InputPin>, trigger: Shared<bool>) {
+fn watch_falling(pin: Shared<rppal::gpio::InputPin>, trigger: Trigger) {
     // Pin is low so needs to go high first before falling edge can happen
     while (*pin.lock().unwrap()).is_low() {}
     // Pin is high, now check for low event
     while (*pin.lock().unwrap()).is_high() {}
 
-    *trigger.lock().unwrap() = true
+    trigger.set()
+}
+
+fn watch_any_edge(pin: Shared<rppal::gpio::InputPin>, trigger: Trigger) {
+    let level = (*pin.lock().unwrap()).read();
+    while (*pin.lock().unwrap()).read() == level {}
+
+    trigger.set()
+}
+
+pub struct EdgeCount {
+    count: u64,
+    thresholds: Vec<(u64, Trigger)>,
+    stopped: bool,
+}
+
+impl EdgeCount {
+    fn new() -> Self {
+        Self {
+            count: 0,
+            thresholds: Vec::new(),
+            stopped: false,
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn reset(&mut self) {
+        self.count = 0;
+    }
+
+    /// Returns a trigger that is set once the count reaches `threshold`
+    pub fn watch_count(&mut self, threshold: u64) -> Trigger {
+        let trigger = Trigger::new(self.count >= threshold);
+
+        if self.count < threshold {
+            self.thresholds.push((threshold, trigger.clone()));
+        }
+
+        trigger
+    }
+
+    /// Stops the thread counting the edges, the count itself stays readable
+    pub fn stop(&mut self) {
+        self.stopped = true;
+    }
+
+    fn increment(&mut self) {
+        self.count += 1;
+
+        let count = self.count;
+        self.thresholds.retain(|(threshold, trigger)| {
+            if count >= *threshold {
+                trigger.set();
+                return false;
+            }
+
+            true
+        });
+    }
+}
+
+/// Accepts a new level of a pin only once it kept it for the debounce time
+struct Debouncer {
+    level: rppal::gpio::Level,
+    debounce: std::time::Duration,
+    /// Moment the pin first differed from the accepted level
+    changed_since: Option<std::time::Instant>,
+}
+
+impl Debouncer {
+    fn new(level: rppal::gpio::Level, debounce: std::time::Duration) -> Self {
+        Self {
+            level,
+            debounce,
+            changed_since: None,
+        }
+    }
+
+    /// Takes a `sample` read at `now`, returns the new level once the change is accepted
+    fn sample(
+        &mut self,
+        sample: rppal::gpio::Level,
+        now: std::time::Instant,
+    ) -> Option<rppal::gpio::Level> {
+        if sample == self.level {
+            self.changed_since = None;
+            return None;
+        }
+
+        let since = *self.changed_since.get_or_insert(now);
+        if now.duration_since(since) < self.debounce {
+            return None;
+        }
+
+        self.level = sample;
+        self.changed_since = None;
+
+        Some(sample)
+    }
+}
+
+/// Returns true when a change to `level` is an edge `watch_type` counts
+fn is_counted(watch_type: &WatchType, level: rppal::gpio::Level) -> bool {
+    match watch_type {
+        WatchType::Rising => level == rppal::gpio::Level::High,
+        WatchType::Falling => level == rppal::gpio::Level::Low,
+        WatchType::AnyEdge => true,
+        WatchType::High | WatchType::Low => false,
+    }
+}
+
+fn count_edges(
+    pin: Shared<rppal::gpio::InputPin>,
+    watch_type: WatchType,
+    debounce: std::time::Duration,
+    count: Shared<EdgeCount>,
+) {
+    let mut debouncer = Debouncer::new((*pin.lock().unwrap()).read(), debounce);
+
+    while !count.lock().unwrap().stopped {
+        let sample = (*pin.lock().unwrap()).read();
+
+        if let Some(level) = debouncer.sample(sample, std::time::Instant::now())
+            && is_counted(&watch_type, level)
+        {
+            count.lock().unwrap().increment();
+        }
+    }
+}
+
+/// Position change for every (previous, current) pair of A/B states, invalid jumps count as no movement
+const QUADRATURE_TABLE: [i64; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+pub struct Quadrature {
+    transitions: i64,
+    transitions_per_step: i64,
+    /// Only changes once the transitions reach the neighbouring detent, so a contact bouncing at a detent does not move it
+    position: i64,
+    last_step: Option<(std::time::Instant, std::time::Duration, i64)>,
+    waiting: Vec<Trigger>,
+    stopped: bool,
+}
+
+impl Quadrature {
+    fn new(transitions_per_step: u32) -> Self {
+        Self {
+            transitions: 0,
+            transitions_per_step: transitions_per_step.max(1) as i64,
+            position: 0,
+            last_step: None,
+            waiting: Vec::new(),
+            stopped: false,
+        }
+    }
+
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: i64) {
+        self.position = position;
+        self.transitions = position.saturating_mul(self.transitions_per_step);
+    }
+
+    /// Returns 1 for clockwise, -1 for counter-clockwise or `None` when there was no movement yet
+    pub fn direction(&self) -> Option<i64> {
+        self.last_step.map(|(_, _, direction)| direction)
+    }
+
+    /// Returns the velocity in steps per second, based on the time between the last two steps
+    pub fn velocity(&self) -> f32 {
+        let (at, interval, direction) = match self.last_step {
+            Some(last_step) => last_step,
+            None => return 0.,
+        };
+
+        let interval = interval.max(at.elapsed());
+        if interval.is_zero() {
+            return 0.;
+        }
+
+        direction as f32 / interval.as_secs_f32()
+    }
+
+    /// Returns a trigger that is set on the next position change
+    pub fn watch_change(&mut self) -> Trigger {
+        let trigger = Trigger::new(false);
+        self.waiting.push(trigger.clone());
+
+        trigger
+    }
+
+    /// Stops the thread decoding the signals, the position itself stays readable
+    pub fn stop(&mut self) {
+        self.stopped = true;
+    }
+
+    fn step(&mut self, delta: i64) {
+        self.transitions = self.transitions.saturating_add(delta);
+
+        // Both directions need a full step of transitions away from the current detent
+        let detent = self.position.saturating_mul(self.transitions_per_step);
+        if self.transitions >= detent.saturating_add(self.transitions_per_step) {
+            self.position += 1;
+        } else if self.transitions <= detent.saturating_sub(self.transitions_per_step) {
+            self.position -= 1;
+        } else {
+            return;
+        }
+
+        let now = std::time::Instant::now();
+        let interval = match self.last_step {
+            Some((at, _, _)) => now - at,
+            // Unknown until a second step happens, so report a standstill
+            None => std::time::Duration::MAX,
+        };
+        self.last_step = Some((now, interval, delta.signum()));
+
+        for trigger in self.waiting.drain(..) {
+            trigger.set();
+        }
+    }
+}
+
+fn decode_quadrature(
+    pin_a: Shared<rppal::gpio::InputPin>,
+    pin_b: Shared<rppal::gpio::InputPin>,
+    quadrature: Shared<Quadrature>,
+) {
+    // Both pins are sampled back to back so the pair describes a single moment
+    let sample = || {
+        let a = (*pin_a.lock().unwrap()).is_high() as usize;
+        let b = (*pin_b.lock().unwrap()).is_high() as usize;
+        (a << 1) | b
+    };
+
+    let mut state = sample();
+
+    while !quadrature.lock().unwrap().stopped {
+        let current = sample();
+        if current == state {
+            continue;
+        }
+
+        let delta = QUADRATURE_TABLE[(state << 2) | current];
+        state = current;
+
+        if delta != 0 {
+            quadrature.lock().unwrap().step(delta);
+        }
+    }
 }
 
 impl Eq for WatchEventKey {}
@@ -128,6 +417,7 @@ pub enum WatchType {
     Low,
     Rising,
     Falling,
+    AnyEdge,
 }
 
 impl Debug for WatchType {
@@ -137,6 +427,7 @@ impl Debug for WatchType {
             Self::Low => write!(f, "Low"),
             Self::Rising => write!(f, "Rising"),
             Self::Falling => write!(f, "Falling"),
+            Self::AnyEdge => write!(f, "AnyEdge"),
         }
     }
 }
@@ -150,6 +441,215 @@ impl std::ops::Not for WatchType {
             WatchType::Low => Self::High,
             WatchType::Rising => Self::Falling,
             WatchType::Falling => Self::Rising,
+            WatchType::AnyEdge => Self::AnyEdge,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rppal::gpio::Level;
+
+    /// A/B states of one full cycle in the positive direction, starting and ending at the detent 00
+    const CLOCKWISE: [usize; 5] = [0b00, 0b10, 0b11, 0b01, 0b00];
+    const COUNTER_CLOCKWISE: [usize; 5] = [0b00, 0b01, 0b11, 0b10, 0b00];
+
+    /// Feeds a sequence of A/B states to `quadrature` like the decoding thread does
+    fn feed(quadrature: &mut Quadrature, states: &[usize]) {
+        for pair in states.windows(2) {
+            let delta = QUADRATURE_TABLE[(pair[0] << 2) | pair[1]];
+
+            if delta != 0 {
+                quadrature.step(delta);
+            }
+        }
+    }
+
+    const DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(5);
+
+    fn ms(ms: u64) -> std::time::Duration {
+        std::time::Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn accepts_a_level_that_is_held_for_the_debounce_time() {
+        let start = std::time::Instant::now();
+        let mut debouncer = Debouncer::new(Level::Low, DEBOUNCE);
+
+        assert_eq!(debouncer.sample(Level::High, start), None);
+        assert_eq!(debouncer.sample(Level::High, start + ms(4)), None);
+        assert_eq!(
+            debouncer.sample(Level::High, start + ms(5)),
+            Some(Level::High)
+        );
+
+        // The accepted level is not reported again
+        assert_eq!(debouncer.sample(Level::High, start + ms(20)), None);
+    }
+
+    #[test]
+    fn restarts_the_debounce_time_when_the_pin_bounces_back() {
+        let start = std::time::Instant::now();
+        let mut debouncer = Debouncer::new(Level::Low, DEBOUNCE);
+
+        assert_eq!(debouncer.sample(Level::High, start), None);
+        assert_eq!(debouncer.sample(Level::Low, start + ms(3)), None);
+        assert_eq!(debouncer.sample(Level::High, start + ms(4)), None);
+        assert_eq!(debouncer.sample(Level::High, start + ms(8)), None);
+        assert_eq!(
+            debouncer.sample(Level::High, start + ms(9)),
+            Some(Level::High)
+        );
+    }
+
+    #[test]
+    fn accepts_every_change_without_debounce() {
+        let start = std::time::Instant::now();
+        let mut debouncer = Debouncer::new(Level::Low, std::time::Duration::ZERO);
+
+        assert_eq!(debouncer.sample(Level::High, start), Some(Level::High));
+        assert_eq!(debouncer.sample(Level::Low, start), Some(Level::Low));
+    }
+
+    #[test]
+    fn counts_the_edges_of_the_watch_type() {
+        assert!(is_counted(&WatchType::Rising, Level::High));
+        assert!(!is_counted(&WatchType::Rising, Level::Low));
+        assert!(is_counted(&WatchType::Falling, Level::Low));
+        assert!(!is_counted(&WatchType::Falling, Level::High));
+        assert!(is_counted(&WatchType::AnyEdge, Level::High));
+        assert!(is_counted(&WatchType::AnyEdge, Level::Low));
+    }
+
+    #[test]
+    fn notifies_once_the_count_reaches_the_threshold() {
+        let mut count = EdgeCount::new();
+        let first = count.watch_count(2);
+        let second = count.watch_count(3);
+
+        count.increment();
+        assert!(!first.is_set());
+
+        count.increment();
+        assert!(first.is_set());
+        assert!(!second.is_set());
+
+        count.increment();
+        assert!(second.is_set());
+        assert_eq!(count.count(), 3);
+    }
+
+    #[test]
+    fn notifies_right_away_when_the_threshold_is_already_reached() {
+        let mut count = EdgeCount::new();
+        count.increment();
+
+        assert!(count.watch_count(1).is_set());
+        assert!(count.thresholds.is_empty());
+
+        count.reset();
+        assert!(!count.watch_count(1).is_set());
+    }
+
+    #[test]
+    fn counts_full_cycles_in_both_directions() {
+        let mut quadrature = Quadrature::new(4);
+
+        feed(&mut quadrature, &CLOCKWISE);
+        feed(&mut quadrature, &CLOCKWISE);
+        assert_eq!(quadrature.position(), 2);
+        assert_eq!(quadrature.direction(), Some(1));
+
+        feed(&mut quadrature, &COUNTER_CLOCKWISE);
+        feed(&mut quadrature, &COUNTER_CLOCKWISE);
+        feed(&mut quadrature, &COUNTER_CLOCKWISE);
+        assert_eq!(quadrature.position(), -1);
+        assert_eq!(quadrature.direction(), Some(-1));
+    }
+
+    #[test]
+    fn counts_every_transition_with_one_transition_per_step() {
+        let mut quadrature = Quadrature::new(1);
+
+        feed(&mut quadrature, &CLOCKWISE);
+        assert_eq!(quadrature.position(), 4);
+
+        feed(&mut quadrature, &COUNTER_CLOCKWISE[..3]);
+        assert_eq!(quadrature.position(), 2);
+    }
+
+    #[test]
+    fn ignores_a_contact_bouncing_at_the_detent() {
+        let mut quadrature = Quadrature::new(4);
+
+        feed(&mut quadrature, &[0b00, 0b10, 0b00, 0b10, 0b00, 0b01, 0b00]);
+        assert_eq!(quadrature.position(), 0);
+        assert_eq!(quadrature.direction(), None);
+
+        // Turning half a step and back does not move either
+        feed(&mut quadrature, &[0b00, 0b10, 0b11, 0b10, 0b00]);
+        assert_eq!(quadrature.position(), 0);
+        assert_eq!(quadrature.direction(), None);
+    }
+
+    #[test]
+    fn counts_a_step_from_bouncing_only_once_at_the_next_detent() {
+        let mut quadrature = Quadrature::new(4);
+
+        // The last transition into the detent bounces
+        feed(
+            &mut quadrature,
+            &[0b00, 0b10, 0b11, 0b01, 0b00, 0b01, 0b00, 0b01, 0b00],
+        );
+        assert_eq!(quadrature.position(), 1);
+    }
+
+    #[test]
+    fn treats_two_bit_jumps_as_no_movement() {
+        for (previous, current) in [(0b00, 0b11), (0b11, 0b00), (0b01, 0b10), (0b10, 0b01)] {
+            assert_eq!(QUADRATURE_TABLE[(previous << 2) | current], 0);
+        }
+
+        // Repeated states do not move either
+        for state in 0..4 {
+            assert_eq!(QUADRATURE_TABLE[(state << 2) | state], 0);
         }
+
+        let mut quadrature = Quadrature::new(4);
+        feed(&mut quadrature, &[0b00, 0b11, 0b00, 0b11, 0b01, 0b00]);
+        assert_eq!(quadrature.position(), 0);
+        assert_eq!(quadrature.direction(), None);
+    }
+
+    #[test]
+    fn velocity_follows_the_time_between_steps() {
+        let mut quadrature = Quadrature::new(1);
+        assert_eq!(quadrature.velocity(), 0.);
+
+        // A single step has no interval yet
+        quadrature.step(1);
+        assert!(quadrature.velocity().abs() < 1e-6);
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        quadrature.step(1);
+        let velocity = quadrature.velocity();
+        assert!(velocity > 0. && velocity <= 50., "{velocity}");
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        quadrature.step(-1);
+        let velocity = quadrature.velocity();
+        assert!(velocity < 0. && velocity >= -50., "{velocity}");
+    }
+
+    #[test]
+    fn velocity_drops_while_standing_still() {
+        let mut quadrature = Quadrature::new(1);
+        quadrature.step(1);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        quadrature.step(1);
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(quadrature.velocity() <= 20., "{}", quadrature.velocity());
     }
 }
@@ -0,0 +1,97 @@
+use crate::ctx::WasiGpioView;
+use crate::impls::GpioImpl;
+use crate::util::{CancelToken, Shared, SharedExt};
+use crate::wasi::gpio::{general, watchdog};
+use std::time::{Duration, Instant};
+
+struct WatchdogState {
+    /// Time the component has to kick the watchdog by
+    deadline: Instant,
+    expired: bool,
+}
+
+/// Watchdog of the policy, the component has to kick it within the interval while it runs
+pub struct WatchdogTimer {
+    interval: Duration,
+    state: Shared<WatchdogState>,
+}
+
+impl WatchdogTimer {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            state: Shared::make_shared(WatchdogState {
+                deadline: Instant::now() + interval,
+                expired: false,
+            }),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn kick(&self) {
+        self.state.lock().unwrap().deadline = Instant::now() + self.interval;
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.state.lock().unwrap().expired
+    }
+
+    /// Starts the interval and spawns the thread that cancels blocking calls and calls `on_expire` once the watchdog is not kicked in time
+    pub fn watch(&self, cancel: CancelToken, on_expire: impl FnOnce() + Send + 'static) {
+        self.kick();
+
+        // A weak reference ends the thread once the context of the component is dropped
+        let state_weak = std::sync::Arc::downgrade(&self.state);
+
+        std::thread::spawn(move || {
+            loop {
+                let deadline = match state_weak.upgrade() {
+                    Some(state) => state.lock().unwrap().deadline,
+                    None => return,
+                };
+
+                if let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                    std::thread::sleep(remaining);
+                    continue;
+                }
+
+                let Some(state) = state_weak.upgrade() else {
+                    return;
+                };
+
+                // Kicked while the thread woke up
+                let mut state = state.lock().unwrap();
+                if state.deadline > Instant::now() {
+                    continue;
+                }
+
+                state.expired = true;
+                cancel.cancel();
+                on_expire();
+                return;
+            }
+        });
+    }
+}
+
+impl<'a, T: WasiGpioView> watchdog::Host for GpioImpl<'a, T> {
+    fn kick(&mut self) -> Result<(), general::GpioError> {
+        match &self.ctx().watchdog {
+            Some(watchdog) => {
+                watchdog.kick();
+                Ok(())
+            }
+            None => Err(general::GpioError::OperationNotSupported),
+        }
+    }
+
+    fn interval_ms(&mut self) -> Option<u64> {
+        self.ctx()
+            .watchdog
+            .as_ref()
+            .map(|watchdog| watchdog.interval().as_millis() as u64)
+    }
+}
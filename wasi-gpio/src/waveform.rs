@@ -0,0 +1,169 @@
+use crate::ctx::WasiGpioView;
+use crate::digital::DigitalOutPin;
+use crate::impls::GpioImpl;
+use crate::util::{CancelToken, Shared, Trigger};
+use crate::wasi::gpio::{general, waveform};
+use crate::{delay, poll, util};
+use wasmtime::component::Resource;
+
+pub struct Waveform {
+    pub done: Trigger,
+    pub cancel: CancelToken,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Waveform {
+    pub fn play(pin: &DigitalOutPin, steps: Vec<waveform::WaveformStep>, repeat: u32) -> Self {
+        let done = Trigger::new(false);
+        let cancel = CancelToken::new();
+
+        // Food for thread
+        let steps = steps
+            .into_iter()
+            .map(|step| {
+                (
+                    pin.get_level(step.state),
+                    std::time::Duration::from_nanos(step.duration_ns),
+                )
+            })
+            .collect();
+        let pin_clone = pin.clone_pin();
+        let done_clone = done.clone();
+        let cancel_clone = cancel.clone();
+
+        let thread = std::thread::spawn(move || {
+            let _ = play_steps(pin_clone, steps, repeat, cancel_clone);
+            done_clone.set();
+        });
+
+        Self {
+            done,
+            cancel,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done.is_set()
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
+
+// The thread also stops when the table is dropped with the store, the pin is released once the drop returns
+impl Drop for Waveform {
+    fn drop(&mut self) {
+        self.cancel();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn play_steps(
+    pin: Shared<rppal::gpio::OutputPin>,
+    steps: Vec<(rppal::gpio::Level, std::time::Duration)>,
+    repeat: u32,
+    cancel: CancelToken,
+) -> Result<(), util::Cancelled> {
+    // Timing is still best effort when the host is not allowed to use realtime scheduling
+    let _ = util::set_realtime_priority();
+
+    // Deadlines are absolute so the error of a single step does not add up over the waveform
+    let mut deadline = std::time::Instant::now();
+
+    for _ in 0..=repeat {
+        for (level, duration) in steps.iter() {
+            cancel.check()?;
+
+            // Only locked for the write, so writes of the component go through between steps and the next step overwrites them
+            pin.lock().unwrap().write(*level);
+
+            deadline += *duration;
+            delay::wait_until_cancellable(deadline, delay::DEFAULT_SPIN_THRESHOLD, &cancel)?;
+        }
+    }
+
+    Ok(())
+}
+
+impl<'a, T: WasiGpioView> waveform::Host for GpioImpl<'a, T> {}
+
+impl<'a, T: WasiGpioView> waveform::HostWaveform for GpioImpl<'a, T> {
+    fn play(
+        &mut self,
+        pin: Resource<DigitalOutPin>,
+        steps: Vec<waveform::WaveformStep>,
+        repeat: u32,
+    ) -> Result<Resource<Waveform>, general::GpioError> {
+        // Repeating nothing would keep the thread busy without ever checking for cancellation
+        if steps.is_empty() {
+            return Err(general::GpioError::Other(
+                "Waveform has no steps".to_string(),
+            ));
+        }
+
+        let label = self
+            .table()
+            .get(&pin)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .config
+            .label
+            .clone();
+
+        // Waveforms toggle the pin on their own thread, past the rate limit and interlocks
+        if self.ctx().policies.is_output_guarded(&label) {
+            return Err(general::GpioError::PinModeNotAllowed);
+        }
+
+        let pin = self
+            .table()
+            .get(&pin)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?;
+
+        let waveform = Waveform::play(pin, steps, repeat);
+
+        self.table()
+            .push(waveform)
+            .map_err(|err| general::GpioError::Other(err.to_string()))
+    }
+
+    fn is_done(&mut self, self_: Resource<Waveform>) -> Result<bool, general::GpioError> {
+        Ok(self
+            .table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .is_done())
+    }
+
+    fn watch_done(
+        &mut self,
+        self_: Resource<Waveform>,
+    ) -> Result<Resource<poll::PollableResource>, general::GpioError> {
+        let trigger = self
+            .table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .done
+            .clone();
+
+        self.push_pollable(poll::Pollable::new(trigger))
+            .map_err(|err| general::GpioError::Other(err.to_string()))
+    }
+
+    fn cancel(&mut self, self_: Resource<Waveform>) -> Result<(), general::GpioError> {
+        self.table()
+            .get(&self_)
+            .map_err(|_| general::GpioError::ResourceInvalidated)?
+            .cancel();
+        Ok(())
+    }
+
+    fn drop(&mut self, rep: Resource<Waveform>) -> wasmtime::Result<()> {
+        self.table().delete(rep).expect("failed to delete resource");
+        Ok(())
+    }
+}
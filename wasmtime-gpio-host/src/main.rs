@@ -1,3 +1,4 @@
+use anyhow::Context;
 use clap::Parser;
 use wasi_gpio::{WasiGpioCtx, WasiGpioView};
 use wasmtime::{
@@ -5,10 +6,20 @@ use wasmtime::{
     component::{Component, Linker},
 };
 use wasmtime_wasi::{
-    ResourceTable, WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView, p2::add_to_linker_sync,
+    ResourceTable, WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView,
+    p2::{add_to_linker_sync, bindings::sync::Command},
 };
 
-use wasi_gpio::policies::Config as HostConfig;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use wasi_gpio::board::Board;
+use wasi_gpio::policies::{
+    self, Config as HostConfig, HostCommand, ImportStatus, Policies, PolicyErrors, World,
+};
+use wasi_gpio::rate_limit::RateLimiters;
+use wasi_gpio::safe_state::SafeStates;
+use wasi_gpio::util::CancelToken;
+use wasi_gpio::watchdog::WatchdogTimer;
 
 struct HostState {
     ctx: WasiCtx,
@@ -25,6 +36,17 @@ impl WasiView for HostState {
     }
 }
 
+/// Puts the pins in their safe states when dropped, also when the host panics or returns early with an error
+struct SafeStateGuard(SafeStates);
+
+impl Drop for SafeStateGuard {
+    fn drop(&mut self) {
+        for (vlabel, err) in self.0.apply() {
+            eprintln!("failed to put `{vlabel}` in its safe state: {err}");
+        }
+    }
+}
+
 // Implement the view trait from your library
 impl WasiGpioView for HostState {
     fn gpio_ctx(&mut self) -> &mut WasiGpioCtx {
@@ -42,13 +64,33 @@ fn main() -> anyhow::Result<()> {
     // 1. Parse CLI arguments
     let config = HostConfig::parse();
 
+    match &config.command {
+        Some(HostCommand::CheckPolicy { policy_file, board }) => {
+            return check_policy(policy_file, Board::from_arg(board.as_deref())?);
+        }
+        Some(HostCommand::CheckComponent {
+            policy_file,
+            component,
+            board,
+            scan_labels,
+        }) => {
+            let board = Board::from_arg(board.as_deref())?;
+            return check_component(policy_file, component, board, *scan_labels);
+        }
+        None => {}
+    }
+
     // 2. Load policies
-    let policies = config.get_policies();
+    let policies = config.get_policies(config.get_board()?)?;
     let component_path = config.get_component_path();
 
+    // Blocked before any thread is spawned so every thread leaves them to the signal thread
+    let signals = block_termination_signals()?;
+
     // 3. Initialize Wasmtime engine
     let mut wasm_config = Config::new();
     wasm_config.wasm_component_model(true);
+    wasm_config.epoch_interruption(true);
     let engine = Engine::new(&wasm_config)?;
     let mut linker = Linker::new(&engine);
 
@@ -58,28 +100,396 @@ fn main() -> anyhow::Result<()> {
     // 5. Add your GPIO bindings
     wasi_gpio::add_to_linker(&mut linker)?;
 
-    // 6. Initialize the Store
-    let wasi = WasiCtxBuilder::new()
-        .inherit_stdio()
-        .inherit_network()
-        .build();
+    // 6. Load the component
+    let component = Component::from_file(&engine, component_path)?;
+
+    // Cancel token of the running component, replaced when it restarts
+    let current = Arc::new(Mutex::new(CancelToken::new()));
+    let timed_out = Arc::new(AtomicBool::new(false));
+
+    // Stop both the wasm code (epoch) and blocking GPIO calls (cancel token) once the timeout passes or a signal arrives
+    let signal = spawn_signal_thread(signals, engine.clone(), current.clone());
+
+    if let Some(timeout) = config.get_timeout() {
+        let engine = engine.clone();
+        let current = current.clone();
+        let timed_out = timed_out.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(timeout);
+            let current = current.lock().unwrap();
+            timed_out.store(true, Ordering::SeqCst);
+            current.cancel();
+            engine.increment_epoch();
+        });
+    }
+
+    let restarts = policies
+        .wasi
+        .watchdog
+        .map_or(0, |watchdog| watchdog.restarts);
+    let mut policies = policies;
+    let mut restarted = 0;
+
+    // Outlives the restarts, a component that gets restarted may not change its pins more often than the policy allows
+    let mut rate_limiters = RateLimiters::default();
+
+    loop {
+        // Declared before the store so unwinding drops the store, and with it the pins, first
+        let safe_states = SafeStateGuard(policies.safe_states());
+
+        // 7. Initialize the Store, every restart gets a fresh one
+        let mut store = Store::new(&engine, new_state(&config, policies, rate_limiters)?);
+        store.set_epoch_deadline(1);
+
+        let cancel = store.data().gpio_ctx.cancel.clone();
+        {
+            let mut current = current.lock().unwrap();
+            *current = cancel.clone();
+
+            // The timeout passed or a signal arrived while the component restarted
+            if timed_out.load(Ordering::SeqCst) || signal.load(Ordering::SeqCst) != 0 {
+                cancel.cancel();
+                engine.increment_epoch();
+            }
+        }
+
+        // The watchdog stops the component the same way, so it also catches busy loops
+        if let Some(watchdog) = &store.data().gpio_ctx.watchdog {
+            let engine = engine.clone();
+            watchdog.watch(cancel.clone(), move || engine.increment_epoch());
+        }
+
+        // 8. Run the export chosen on the command line or the entry point of the component's world
+        let result = run(&mut store, &linker, &component, &config);
+
+        if config.delay_report && (result.is_ok() || cancel.is_cancelled()) {
+            let stats = store.data().gpio_ctx.delay.stats();
+            eprintln!(
+                "delays: {}, mean error: {:?}, max error: {:?}",
+                stats.count,
+                stats.mean_error(),
+                stats.max_error
+            );
+        }
 
-    let state = HostState {
-        ctx: wasi,
+        // 9. Drop every resource so their pins are free, then put the pins in their safe states
+        let HostState {
+            table, gpio_ctx, ..
+        } = store.into_data();
+        drop(table);
+        drop(safe_states);
+
+        let expired = gpio_ctx
+            .watchdog
+            .as_ref()
+            .is_some_and(WatchdogTimer::is_expired);
+        policies = gpio_ctx.policies;
+        rate_limiters = gpio_ctx.rate_limiters;
+
+        match result {
+            Ok(()) => return Ok(()),
+            Err(_) if signal.load(Ordering::SeqCst) != 0 => {
+                let signal = signal.load(Ordering::SeqCst);
+                eprintln!("component stopped by signal {signal}");
+                std::process::exit(128 + signal);
+            }
+            Err(_) if timed_out.load(Ordering::SeqCst) => {
+                eprintln!("component stopped after timeout");
+                return Ok(());
+            }
+            Err(_) if expired && restarted < restarts => {
+                restarted += 1;
+                eprintln!("watchdog expired, restarting the component ({restarted} of {restarts})");
+            }
+            Err(_) if expired => {
+                return Err(anyhow::anyhow!(
+                    "component stopped after the watchdog expired"
+                ));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Builds the state a run of the component starts with, the policies and rate limiters are moved into it
+fn new_state(
+    config: &HostConfig,
+    policies: Policies,
+    rate_limiters: RateLimiters,
+) -> anyhow::Result<HostState> {
+    let mut wasi = WasiCtxBuilder::new();
+    wasi.inherit_stdio()
+        .arg(config.get_component_path())
+        .args(&config.args);
+
+    for (key, value) in &config.env {
+        wasi.env(key, value);
+    }
+
+    policies.grant_capabilities(&mut wasi)?;
+
+    for preopen in config.get_preopens() {
+        policies.check_preopen(&preopen)?;
+        preopen.grant(&mut wasi)?;
+    }
+
+    let mut gpio_ctx = WasiGpioCtx::new(policies);
+    gpio_ctx.delay = config.get_delay();
+    gpio_ctx.rate_limiters = rate_limiters;
+
+    Ok(HostState {
+        ctx: wasi.build(),
         table: ResourceTable::new(),
-        gpio_ctx: WasiGpioCtx::new(policies),
+        gpio_ctx,
+    })
+}
+
+/// Instantiates the component and calls the export selected by `--invoke`, or the entry point of its world
+fn run(
+    store: &mut Store<HostState>,
+    linker: &Linker<HostState>,
+    component: &Component,
+    config: &HostConfig,
+) -> anyhow::Result<()> {
+    if let Some(export) = &config.invoke {
+        let instance = linker.instantiate(&mut *store, component)?;
+
+        let index = match export.split_once('#') {
+            Some((interface, function)) => instance
+                .get_export_index(&mut *store, None, interface)
+                .and_then(|interface| {
+                    instance.get_export_index(&mut *store, Some(&interface), function)
+                }),
+            None => instance.get_export_index(&mut *store, None, export),
+        }
+        .with_context(|| format!("component has no export named `{export}`"))?;
+
+        let func = instance.get_typed_func::<(), ()>(&mut *store, index)?;
+        return func.call(&mut *store, ());
+    }
+
+    let world = match config.world {
+        World::Auto if exports_command(store.engine(), component) => World::Command,
+        World::Auto => World::Rpi,
+        world => world,
     };
 
-    let mut store = Store::new(&engine, state);
+    match world {
+        World::Command => {
+            let command = Command::instantiate(&mut *store, component, linker)?;
+            command
+                .wasi_cli_run()
+                .call_run(&mut *store)?
+                .map_err(|()| anyhow::anyhow!("component exited with an error"))
+        }
+        _ => {
+            let instance = linker.instantiate(&mut *store, component)?;
+            let start = instance.get_typed_func::<(), ()>(&mut *store, "start")?;
+            start.call(&mut *store, ())
+        }
+    }
+}
 
-    // 7. Load and instantiate
-    let component = Component::from_file(&engine, component_path)?;
-    let instance = linker.instantiate(&mut store, &component)?;
+/// Blocks SIGINT and SIGTERM in the calling thread and the threads it spawns, returns the blocked set
+fn block_termination_signals() -> anyhow::Result<libc::sigset_t> {
+    unsafe {
+        let mut signals: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGINT);
+        libc::sigaddset(&mut signals, libc::SIGTERM);
+
+        match libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut()) {
+            0 => Ok(signals),
+            err => Err(std::io::Error::from_raw_os_error(err))
+                .context("failed to block termination signals"),
+        }
+    }
+}
+
+/// Stops the component on the first of `signals` so the host can apply the safe states, returns the received signal or 0
+fn spawn_signal_thread(
+    signals: libc::sigset_t,
+    engine: Engine,
+    current: Arc<Mutex<CancelToken>>,
+) -> Arc<AtomicI32> {
+    let received = Arc::new(AtomicI32::new(0));
+    let received_clone = received.clone();
+
+    std::thread::spawn(move || {
+        let mut signal = 0;
+
+        if unsafe { libc::sigwait(&signals, &mut signal) } != 0 {
+            return;
+        }
+
+        {
+            let current = current.lock().unwrap();
+            received_clone.store(signal, Ordering::SeqCst);
+            current.cancel();
+            engine.increment_epoch();
+        }
+
+        // Exiting here would leave the pins as the component drove them, only SIGKILL skips the safe states
+        while unsafe { libc::sigwait(&signals, &mut signal) } == 0 {
+            eprintln!(
+                "waiting for the component to stop, SIGKILL exits without applying the safe states"
+            );
+        }
+    });
+
+    received
+}
+
+fn exports_command(engine: &Engine, component: &Component) -> bool {
+    component
+        .component_type()
+        .exports(engine)
+        .any(|(name, _)| name.starts_with("wasi:cli/run@"))
+}
 
-    // 8. Run the 'start' export (Note: your guest.wit exports 'start', not 'run')
-    // If your guest.wit says `export start: func();`, use "start" here.
-    let run = instance.get_typed_func::<(), ()>(&mut store, "start")?;
-    run.call(&mut store, ())?;
+/// Prints how the virtual labels of a policy file resolve and fails when the policy has problems
+fn check_policy(policy_file: &str, board: Board) -> anyhow::Result<()> {
+    let (mut policies, source) = policies::read(policy_file)?;
+    policies.board = board;
+
+    println!("board: {}", policies.board.name);
+
+    println!(
+        "{:<16} {:<10} {:<8} {:<8} MODES",
+        "VLABEL", "PLABEL", "BCM", "HEADER"
+    );
+    for entry in policies.wasi.gpio.iter() {
+        let (bcm, header) = policies.board.resolve(entry.plabel.get_ref()).map_or(
+            ("-".to_string(), "-".to_string()),
+            |pin| {
+                (
+                    format!("GPIO{}", pin.bcm),
+                    pin.header
+                        .map_or("-".to_string(), |header| header.to_string()),
+                )
+            },
+        );
+        let modes: Vec<_> = entry.modes.iter().map(|mode| mode.name()).collect();
+
+        println!(
+            "{:<16} {:<10} {:<8} {:<8} {}",
+            entry.vlabel.get_ref(),
+            entry.plabel.get_ref(),
+            bcm,
+            header,
+            modes.join(", ")
+        );
+    }
+
+    for port in policies.wasi.port.iter() {
+        let pins: Vec<_> = port.pins.iter().map(|pin| pin.get_ref().as_str()).collect();
+        let modes: Vec<_> = port.modes.iter().map(|mode| mode.name()).collect();
+
+        println!(
+            "{:<16} port of {}, {}",
+            port.vlabel.get_ref(),
+            pins.join(", "),
+            modes.join(", ")
+        );
+    }
+
+    let mut errors = policies.validate();
+    errors.extend(policies.check_board());
+
+    let (warnings, errors): (Vec<_>, Vec<_>) = errors
+        .into_iter()
+        .partition(|error| error.kind.is_warning());
+
+    let warnings = PolicyErrors {
+        file: policy_file.to_string(),
+        source: source.clone(),
+        errors: warnings,
+    };
+    for warning in warnings.to_string().lines() {
+        eprintln!("warning: {warning}");
+    }
+
+    if errors.is_empty() {
+        return Ok(());
+    }
 
-    Ok(())
+    Err(PolicyErrors {
+        file: policy_file.to_string(),
+        source,
+        errors,
+    }
+    .into())
+}
+
+/// Prints what a component imports and which virtual labels it uses compared to what the policy grants
+fn check_component(
+    policy_file: &str,
+    component_path: &str,
+    board: Board,
+    scan_labels: bool,
+) -> anyhow::Result<()> {
+    let policies = policies::load(policy_file, board)?;
+
+    let mut wasm_config = Config::new();
+    wasm_config.wasm_component_model(true);
+    let engine = Engine::new(&wasm_config)?;
+
+    let bytes = std::fs::read(component_path)
+        .with_context(|| format!("failed to read from `{component_path}`"))?;
+    // Text components are scanned in their binary form, where the string constants are in data segments
+    let bytes = wat::parse_bytes(&bytes)?;
+    let component = Component::new(&engine, &bytes)?;
+
+    let report = policies.check_component(&engine, &component, scan_labels.then_some(&bytes[..]));
+
+    // Catches imports neither wasmtime-wasi nor wasi-gpio provide, and mismatched types
+    let mut linker = Linker::<HostState>::new(&engine);
+    add_to_linker_sync(&mut linker)?;
+    wasi_gpio::add_to_linker(&mut linker)?;
+    let link_error = linker.instantiate_pre(&component).err();
+
+    for import in report.imports.iter() {
+        match &import.status {
+            ImportStatus::Granted => println!("{:<40} granted", import.name),
+            ImportStatus::Denied(reason) => println!("{:<40} denied: {}", import.name, reason),
+            ImportStatus::Unavailable => {
+                println!("{:<40} not provided by this host", import.name)
+            }
+        }
+    }
+
+    if let Some(referenced) = &report.referenced_labels {
+        println!("labels found in the component: {}", referenced.join(", "));
+    }
+
+    if let Some(unreferenced) = &report.unreferenced_labels {
+        println!(
+            "labels not found in the component: {}",
+            unreferenced.join(", ")
+        );
+    }
+
+    for (label, modes) in report.unusable_labels.iter().flatten() {
+        println!(
+            "label `{label}` is granted as {}, which no import of the component uses",
+            modes.join(", ")
+        );
+    }
+
+    for (constant, label) in report.unknown_labels.iter().flatten() {
+        println!(
+            "`{constant}` is not declared by the policy, it looks like a misspelling of `{label}`"
+        );
+    }
+
+    if let Some(err) = &link_error {
+        println!("the component does not link: {err:#}");
+    }
+
+    match report.is_compatible() && link_error.is_none() {
+        true => Ok(()),
+        false => Err(anyhow::anyhow!(
+            "the component needs imports or pin modes that are not granted"
+        )),
+    }
 }